@@ -0,0 +1,42 @@
+//! Authentication probability, verification delay and overhead of the schemes over lossy channels.
+//!
+//! Usage: `cargo run --release --example sim [trace file]`, where the trace has a character per
+//! packet, `1` if lost and `0` if received.
+
+use alta::scheme::Scheme;
+use alta::sim::{Bernoulli, GilbertElliott, LossModel, Simulation, Trace};
+
+/// Creates a new channel for each simulation, with the same losses.
+type NewChannel = Box<dyn Fn() -> Box<dyn LossModel>>;
+
+fn main() {
+    let trace = std::env::args().nth(1).map(|path| Trace::from_file(path).expect("cannot read the trace"));
+
+    let mut channels: Vec<(String, NewChannel)> = Vec::new();
+    for loss_rate in [0.01, 0.05, 0.1, 0.2] {
+        channels.push((format!("bernoulli {loss_rate}"), Box::new(move || Box::new(Bernoulli::new(loss_rate, 1)))));
+    }
+    for (p, r) in [(0.01, 0.5), (0.02, 0.25), (0.05, 0.2)] {
+        channels.push((format!("gilbert p={p} r={r}"), Box::new(move || Box::new(GilbertElliott::gilbert(p, r, 1)))));
+    }
+    if let Some(trace) = trace {
+        channels.push(("trace".to_string(), Box::new(move || Box::new(trace.clone()))));
+    }
+
+    println!("channel,a,p,auth_probability,mean_delay,max_delay,overhead");
+    for (name, channel) in channels.iter() {
+        for p in [5, 10] {
+            for a in 2..=6 {
+                let sim = Simulation::new(Scheme::new(a, p).unwrap());
+                let res = sim.run(channel().as_mut());
+                println!(
+                    "{name},{a},{p},{:.4},{:.1},{},{:.1}",
+                    res.auth_probability(),
+                    res.mean_delay,
+                    res.max_delay,
+                    res.overhead()
+                );
+            }
+        }
+    }
+}
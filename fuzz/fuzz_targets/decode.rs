@@ -0,0 +1,26 @@
+#![no_main]
+
+use alta::buffer::bytes::WireFormat;
+use alta::buffer::BufferEntry;
+use alta::graph::Graph;
+use alta::hash::Sha256;
+use alta::scheme::Scheme;
+use bytes::{Bytes, BytesMut};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let graph = Graph::new(Scheme::default());
+
+    for format in [WireFormat::Versioned, WireFormat::Legacy] {
+        // Malformed packets must be rejected without panicking.
+        let Ok(entry) = BufferEntry::<Sha256>::decode_with(Bytes::copy_from_slice(data), &graph, format) else {
+            continue;
+        };
+
+        // Decoded packets round-trip with the encoding.
+        let mut buf = BytesMut::from(entry.payload().unwrap_or_default());
+        entry.encode_with(&mut buf, &graph, format);
+        let decoded = BufferEntry::decode_with(buf.freeze(), &graph, format).expect("re-encoded node must decode");
+        assert_eq!(decoded, entry);
+    }
+});
@@ -1,4 +1,10 @@
 //! This modules handles the wire format of the BufferEntry nodes.
+//!
+//! The ALTA fields are appended to the payload and read from the end of the packet.
+//! The versioned format ends with a fixed [`Trailer`] describing the stream, so that receivers
+//! can reject packets from newer senders, or adapt to their scheme and algorithms.
+//! The former unversioned format, where the receiver infers everything from its own graph,
+//! is still available with [`WireFormat::Legacy`].
 
 use std::collections::VecDeque;
 
@@ -7,89 +13,490 @@ use bytes::{BufMut, Bytes, BytesMut};
 use integer_encoding::VarInt;
 
 use super::BufferEntry;
-use crate::{Error, State};
+use crate::graph::{Graph, Switch};
+use crate::hash::PacketHasher;
+use crate::sign::SignatureAlgorithm;
+use crate::{Error, PktHash, Signature, State};
 use crate::Result;
 
-impl BufferEntry {
-    /// Encodes a node into bytes.
-    pub fn encode(&self, buf: &mut BytesMut) {
-        let mut tmp = [0u8; 8];
+/// Version of the wire format written in the trailer.
+pub const WIRE_VERSION: u8 = 1;
+
+/// Flag of the trailer set if the node announces a scheme switch.
+const FLAG_SWITCH: u8 = 0x01;
+
+/// Flag of the trailer set if the ID is truncated to its lowest 16 bits.
+const FLAG_ID16: u8 = 0x02;
+
+/// Flag of the trailer set if the ID is truncated to its lowest 32 bits.
+const FLAG_ID32: u8 = 0x04;
+
+/// Flag of the trailer set if the node only completes a row, without payload of the application.
+const FLAG_PADDING: u8 = 0x08;
+
+/// Length of a scheme switch announcement: start ID (u64), a (u16) and p (u16).
+const SWITCH_LEN: usize = 12;
+
+/// Tag of a scheme switch announcement in the legacy format, distinct from the signature algorithm identifiers.
+const LEGACY_SWITCH_TAG: u8 = 0x80;
+
+/// Tag of a padding node in the legacy format, distinct from the other tags.
+const LEGACY_PADDING_TAG: u8 = 0x81;
+
+/// Wire format of the nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireFormat {
+    /// Format ending with a versioned trailer.
+    #[default]
+    Versioned,
+
+    /// Versioned format where the ID is truncated to its lowest bits.
+    /// The receiver expands it to the closest ID to the one it expects next,
+    /// so its window must be shorter than half the range of the truncated IDs.
+    /// The signed nodes carry their full ID, so that a receiver joining late anchors its window
+    /// on the first one whose signature verifies.
+    Compact(IdWidth),
+
+    /// Unversioned format, where the number of hashes is inferred from the graph
+    /// and the signature length from the total length of the ALTA fields.
+    Legacy,
+}
+
+/// Width of a truncated ID on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdWidth {
+    /// Lowest 16 bits of the ID, in big-endian.
+    Bits16,
+
+    /// Lowest 32 bits of the ID, in big-endian.
+    Bits32,
+}
+
+impl IdWidth {
+    /// Number of bits of the truncated ID.
+    pub fn bits(&self) -> u32 {
+        match self {
+            Self::Bits16 => 16,
+            Self::Bits32 => 32,
+        }
+    }
+
+    /// Expands the truncated ID to the closest ID to the expected one, wrapping around after `u64::MAX`.
+    pub fn expand(&self, truncated: u64, expected: u64) -> u64 {
+        let range = 1u64 << self.bits();
+        let candidate = (expected & !(range - 1)) | truncated;
+        let distance = candidate.wrapping_sub(expected) as i64;
+        if distance <= -(range as i64 / 2) {
+            candidate.wrapping_add(range)
+        } else if distance > range as i64 / 2 {
+            candidate.wrapping_sub(range)
+        } else {
+            candidate
+        }
+    }
+}
+
+/// Fixed fields ending a packet in the versioned wire format.
+///
+/// A packet has the following layout, integers in big-endian and varints written in reverse
+/// order to be read from the end:
+///
+/// ```text
+/// payload | hashes | [switch start (u64) | a (u16) | p (u16)] | [signature | signature length (varint)]
+///         | id (varint, u16 or u32) | a (u16) | p (u16) | nb hashes (u16) | hash algorithm (u8)
+///         | signature algorithm (u8, 0 if none) | flags (u8) | version (u8)
+/// ```
+///
+/// The flags tell whether the node announces a scheme switch, whether the ID is truncated, and
+/// whether the node is padding, in which case its payload is empty. The ID of a signed node is
+/// never truncated.
+///
+/// The version comes last so that receivers check it before anything else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trailer {
+    /// Version of the wire format.
+    pub version: u8,
+
+    /// Identifier of the hash algorithm, see `PacketHasher::ID`.
+    pub hash_algorithm: u8,
+
+    /// Algorithm of the signature carried by the node, if any.
+    pub signature_algorithm: Option<SignatureAlgorithm>,
+
+    /// The `a` parameter of the scheme of the node.
+    pub a: usize,
+
+    /// The `p` parameter of the scheme of the node.
+    pub p: usize,
+
+    /// Number of hashes carried by the node.
+    pub nb_hashes: usize,
+
+    /// Whether the node announces a scheme switch.
+    pub has_switch: bool,
+
+    /// Width of the ID, if truncated.
+    pub id_width: Option<IdWidth>,
+
+    /// Whether the node only completes a row, without payload of the application.
+    pub is_padding: bool,
+}
+
+impl Trailer {
+    /// Length of the trailer, in bytes.
+    pub const LEN: usize = 10;
+
+    /// Reads the trailer at the end of the packet.
+    /// Returns `Unsupported` if the packet uses another version of the wire format or an unknown
+    /// signature algorithm, and `Decoding` if it is truncated or malformed.
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let mut trailer = buf.len().checked_sub(Self::LEN).map(|start| &buf[start..]).ok_or(Error::Decoding)?;
+        if trailer[Self::LEN - 1] != WIRE_VERSION {
+            return Err(Error::Unsupported);
+        }
+
+        let a = trailer.get_u16() as usize;
+        let p = trailer.get_u16() as usize;
+        let nb_hashes = trailer.get_u16() as usize;
+        let hash_algorithm = trailer.get_u8();
+        let signature_algorithm = match trailer.get_u8() {
+            0 => None,
+            id => Some(SignatureAlgorithm::try_from(id).map_err(|_| Error::Unsupported)?),
+        };
+        let flags = trailer.get_u8();
+        let id_width = match flags & (FLAG_ID16 | FLAG_ID32) {
+            0 => None,
+            FLAG_ID16 => Some(IdWidth::Bits16),
+            FLAG_ID32 => Some(IdWidth::Bits32),
+            _ => return Err(Error::Decoding),
+        };
+        if flags & !(FLAG_SWITCH | FLAG_ID16 | FLAG_ID32 | FLAG_PADDING) != 0 {
+            return Err(Error::Decoding);
+        }
+
+        Ok(Self {
+            version: trailer.get_u8(),
+            hash_algorithm,
+            signature_algorithm,
+            a,
+            p,
+            nb_hashes,
+            has_switch: flags & FLAG_SWITCH != 0,
+            id_width,
+            is_padding: flags & FLAG_PADDING != 0,
+        })
+    }
+
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16(self.a as u16);
+        buf.put_u16(self.p as u16);
+        buf.put_u16(self.nb_hashes as u16);
+        buf.put_u8(self.hash_algorithm);
+        buf.put_u8(self.signature_algorithm.map_or(0, |algorithm| algorithm as u8));
+        let mut flags = if self.has_switch { FLAG_SWITCH } else { 0 };
+        flags |= match self.id_width {
+            None => 0,
+            Some(IdWidth::Bits16) => FLAG_ID16,
+            Some(IdWidth::Bits32) => FLAG_ID32,
+        };
+        if self.is_padding {
+            flags |= FLAG_PADDING;
+        }
+        buf.put_u8(flags);
+        buf.put_u8(self.version);
+    }
+}
+
+impl<H: PacketHasher> BufferEntry<H> {
+    /// Encodes a node into bytes, in the versioned wire format.
+    /// The graph gives the scheme of the node.
+    pub fn encode(&self, buf: &mut BytesMut, graph: &Graph) {
+        self.encode_with(buf, graph, WireFormat::Versioned);
+    }
+
+    /// Encodes a node into bytes, in the wire format.
+    pub fn encode_with(&self, buf: &mut BytesMut, graph: &Graph, format: WireFormat) {
+        match format {
+            WireFormat::Versioned => self.encode_versioned(buf, graph, None),
+            WireFormat::Compact(width) => self.encode_versioned(buf, graph, Some(width)),
+            WireFormat::Legacy => self.encode_legacy(buf),
+        }
+    }
+
+    /// Decodes a node from bytes, in the versioned wire format.
+    /// Returns `Unsupported` if the packet uses another version of the wire format or unknown algorithms,
+    /// `InvalidScheme` if the scheme of the node does not match the graph,
+    /// and `Decoding` if the buffer is truncated or malformed, or if the ID precedes the start of the graph.
+    pub fn decode(buf: Bytes, graph: &Graph) -> Result<Self> {
+        Self::decode_with(buf, graph, WireFormat::Versioned)
+    }
+
+    /// Decodes a node from bytes, in the wire format.
+    /// A truncated ID is expanded to the closest ID to the start of the graph.
+    pub fn decode_with(buf: Bytes, graph: &Graph, format: WireFormat) -> Result<Self> {
+        Self::decode_near(buf, graph, format, graph.start())
+    }
+
+    /// Decodes a node from bytes, in the wire format.
+    /// A truncated ID is expanded to the closest ID to the expected one, e.g., `Buffer::expected_id`.
+    /// The versioned and compact formats are decoded alike, since the trailer tells the width of the ID.
+    pub fn decode_near(buf: Bytes, graph: &Graph, format: WireFormat, expected_id: u64) -> Result<Self> {
+        match format {
+            WireFormat::Versioned | WireFormat::Compact(_) => Self::decode_versioned(buf, graph, expected_id),
+            WireFormat::Legacy => Self::decode_legacy(buf, graph),
+        }
+    }
+
+    fn encode_versioned(&self, buf: &mut BytesMut, graph: &Graph, id_width: Option<IdWidth>) {
+        let id_width = id_width.filter(|_| self.signature.is_none());
+        for hash in self.hashes.iter() {
+            buf.put(hash.as_ref());
+        }
+
+        if let Some(switch) = self.switch.as_ref() {
+            buf.put_u64(switch.start);
+            buf.put_u16(switch.a as u16);
+            buf.put_u16(switch.p as u16);
+        }
+
+        if let Some(signature) = self.signature.as_ref() {
+            buf.put(&signature.bytes[..]);
+            put_reversed_var(buf, signature.bytes.len() as u64);
+        }
+
+        match id_width {
+            None => put_reversed_var(buf, self.id),
+            Some(IdWidth::Bits16) => buf.put_u16(self.id as u16),
+            Some(IdWidth::Bits32) => buf.put_u32(self.id as u32),
+        }
+
+        let scheme = graph.scheme_at(self.id);
+        let trailer = Trailer {
+            version: WIRE_VERSION,
+            hash_algorithm: H::ID,
+            signature_algorithm: self.signature.as_ref().map(|signature| signature.algorithm),
+            a: scheme.a(),
+            p: scheme.p(),
+            nb_hashes: self.hashes.len(),
+            has_switch: self.switch.is_some(),
+            id_width,
+            is_padding: self.is_padding,
+        };
+        trailer.encode(buf);
+    }
+
+    fn decode_versioned(buf: Bytes, graph: &Graph, expected_id: u64) -> Result<Self> {
+        let trailer = Trailer::decode(&buf)?;
+        if trailer.hash_algorithm != H::ID {
+            return Err(Error::Unsupported);
+        }
+        let mut end = buf.len() - Trailer::LEN;
+
+        let id = match trailer.id_width {
+            None => {
+                let (id, len_id) = get_reversed_var(&buf[..end])?;
+                end -= len_id;
+                id
+            },
+            Some(width) => {
+                let start = checked_start(end, width.bits() as u64 / 8)?;
+                let truncated = buf[start..end].iter().fold(0, |id, byte| (id << 8) | *byte as u64);
+                end = start;
+                let id = width.expand(truncated, expected_id);
+                // The closest ID may precede the start, at the beginning of the stream.
+                match graph.precedes_start(id) {
+                    true => id.wrapping_add(1 << width.bits()),
+                    false => id,
+                }
+            },
+        };
+        if graph.precedes_start(id) {
+            return Err(Error::Decoding);
+        }
+
+        let signature = match trailer.signature_algorithm {
+            Some(algorithm) => {
+                let (sig_len, len_len) = get_reversed_var(&buf[..end])?;
+                end -= len_len;
+                let start = checked_start(end, sig_len)?;
+                if start == end {
+                    return Err(Error::Decoding);
+                }
+                let bytes = buf[start..end].to_vec();
+                end = start;
+                Some(Signature { algorithm, bytes })
+            }
+            None => None,
+        };
+
+        let switch = if trailer.has_switch {
+            let start = checked_start(end, SWITCH_LEN as u64)?;
+            let mut tmp = &buf[start..end];
+            end = start;
+            Some(Switch {
+                start: tmp.get_u64(),
+                a: tmp.get_u16() as usize,
+                p: tmp.get_u16() as usize,
+            })
+        } else {
+            None
+        };
+
+        // The node depends on the switch it announces, which may not be authenticated yet.
+        let switched = switch.and_then(|switch| {
+            let mut switched = graph.clone();
+            switched.switch(switch).ok().map(|()| switched)
+        });
+        let graph = switched.as_ref().unwrap_or(graph);
+
+        // The receiver must follow the same scheme as the sender.
+        let scheme = graph.scheme_at(id);
+        if (trailer.a, trailer.p) != (scheme.a(), scheme.p()) {
+            return Err(Error::InvalidScheme);
+        }
+        let dependencies = graph.dependencies_in(id);
+        if dependencies.len() != trailer.nb_hashes {
+            return Err(Error::Decoding);
+        }
+
+        let start = checked_start(end, (trailer.nb_hashes * H::DIGEST_LEN) as u64)?;
+        let hashes = buf[start..end]
+            .chunks_exact(H::DIGEST_LEN)
+            .map(|hash| hash.try_into().map_err(|_| Error::Decoding))
+            .collect::<Result<VecDeque<PktHash<H>>>>()?;
+        if trailer.is_padding && start > 0 {
+            return Err(Error::Decoding);
+        }
+
+        Ok(Self {
+            id,
+            hashes,
+            signature,
+            payload: Some(buf[..start].to_vec()),
+            dependencies,
+            state: State::NotReady,
+            switch,
+            is_padding: trailer.is_padding,
+            received_at: None,
+        })
+    }
+
+    fn encode_legacy(&self, buf: &mut BytesMut) {
         let mut bytes_len = 0;
 
         // Encode the hashes.
         // The decoding is responsible to know the number of hashes in the buffer
         // since it knows the scheme.
         for hash in self.hashes.iter() {
-            buf.put(&hash[..]);
-            bytes_len += 32;
+            buf.put(hash.as_ref());
+            bytes_len += H::DIGEST_LEN;
+        }
+
+        // Tag the padding nodes.
+        if self.is_padding {
+            buf.put_u8(LEGACY_PADDING_TAG);
+            bytes_len += 1;
+        }
+
+        // Encode the scheme switch, if there is one.
+        // The tag is followed by the start ID (u64), a (u16) and p (u16) in big-endian.
+        if let Some(switch) = self.switch.as_ref() {
+            buf.put_u8(LEGACY_SWITCH_TAG);
+            buf.put_u64(switch.start);
+            buf.put_u16(switch.a as u16);
+            buf.put_u16(switch.p as u16);
+            bytes_len += 1 + SWITCH_LEN;
         }
 
         // Encode the signature, if there is one.
+        // The algorithm identifier comes first, followed by the variable-length signature.
+        // Its length is infered from the total length of the ALTA fields.
         if let Some(signature) = self.signature.as_ref() {
-            buf.put(&signature[..]);
-            bytes_len += 64;
+            buf.put_u8(signature.algorithm as u8);
+            buf.put(&signature.bytes[..]);
+            bytes_len += 1 + signature.bytes.len();
         }
 
         // Encode the length.
-        let len = (bytes_len as u64).encode_var(&mut tmp);
-
-        // Reverse the buffer to read by the end.
-        tmp.reverse();
-        buf.put(&tmp[tmp.len() - len..]);
+        put_reversed_var(buf, bytes_len as u64);
 
         // Encode ID.
         // We finish by the ID so that we know exactly, by infering from
         // the scheme, where is the boundary of the payload.
-        tmp = [0u8; 8];
-        let len = self.id.encode_var(&mut tmp);
-
-        // Reverse the buffer to read by the end.
-        tmp.reverse();
-        buf.put(&tmp[tmp.len() - len..]);
+        put_reversed_var(buf, self.id);
     }
 
-    /// Decodes a node from bytes.
-    pub fn decode(mut buf: Bytes) -> Result<Self> {
+    fn decode_legacy(mut buf: Bytes, graph: &Graph) -> Result<Self> {
         // Start by decoding the ID.
         // The ID is encoded in the last bytes of the buffer, in reverse order.
-        // The maximum length is 8 bytes.
-        let mut tmp = [0u8; 8];
-        tmp.copy_from_slice(&buf[buf.len() - 8..]);
-        tmp.reverse();
-        let (id, len_id) = u64::decode_var(&tmp[..]).ok_or(Error::Decoding)?;
+        let (id, len_id) = get_reversed_var(&buf)?;
+        if graph.precedes_start(id) {
+            return Err(Error::Decoding);
+        }
 
         // Get the length.
-        tmp = [0u8; 8];
-        tmp.copy_from_slice(&buf[buf.len() - len_id - 8..buf.len() - len_id]);
-        tmp.reverse();
-        let (bytes_len, len_len) = u64::decode_var(&tmp[..]).ok_or(Error::Decoding)?;
+        let (bytes_len, len_len) = get_reversed_var(&buf[..buf.len() - len_id])?;
 
         // Read remaining, infering the total length.
-        let split_idx = buf.len() - len_id - len_len - bytes_len as usize;
+        let split_idx = usize::try_from(bytes_len)
+            .ok()
+            .and_then(|bytes_len| buf.len().checked_sub(len_id + len_len + bytes_len))
+            .ok_or(Error::Decoding)?;
         let mut buf_alta = buf.split_off(split_idx);
 
         // Further need to split the buf_alta to remove the ID and length previously read.
         let _ = buf_alta.split_off(buf_alta.len() - len_id - len_len);
 
         // Get the number of hashes by infering from the ID.
-        let nb_hashes = BufferEntry::dependencies_in(id).len();
+        let dependencies = graph.dependencies_in(id);
+        let nb_hashes = dependencies.len();
 
         // Get the hashes.
-        let mut hashes: VecDeque<[u8; 32]> = VecDeque::with_capacity(nb_hashes);
+        let mut hashes: VecDeque<PktHash<H>> = VecDeque::with_capacity(nb_hashes);
         for _ in 0..nb_hashes {
             let hash = buf_alta
-                .get(0..32)
+                .get(0..H::DIGEST_LEN)
                 .ok_or(Error::Decoding)?
                 .try_into()
                 .map_err(|_| Error::Decoding)?;
             hashes.push_back(hash);
-            buf_alta.advance(32);
+            buf_alta.advance(H::DIGEST_LEN);
+        }
+
+        // Padding nodes have no payload.
+        let is_padding = buf_alta.first() == Some(&LEGACY_PADDING_TAG);
+        if is_padding {
+            if !buf.is_empty() {
+                return Err(Error::Decoding);
+            }
+            buf_alta.advance(1);
         }
 
+        // Get the scheme switch, if there is one.
+        let switch = if buf_alta.first() == Some(&LEGACY_SWITCH_TAG) {
+            if buf_alta.len() < 1 + SWITCH_LEN {
+                return Err(Error::Decoding);
+            }
+            buf_alta.advance(1);
+            Some(Switch {
+                start: buf_alta.get_u64(),
+                a: buf_alta.get_u16() as usize,
+                p: buf_alta.get_u16() as usize,
+            })
+        } else {
+            None
+        };
+
         // Get the signature, if there is one.
         let signature = if !buf_alta.is_empty() {
-            let sign = buf_alta.get(0..64).ok_or(Error::Decoding)?.try_into().map_err(|_| Error::Decoding)?;
-            Some(sign)
+            let algorithm = SignatureAlgorithm::try_from(buf_alta.get_u8())?;
+            if buf_alta.is_empty() {
+                return Err(Error::Decoding);
+            }
+            Some(Signature {
+                algorithm,
+                bytes: buf_alta.to_vec(),
+            })
         } else {
             None
         };
@@ -99,57 +506,278 @@ impl BufferEntry {
             hashes,
             signature,
             payload: Some(buf.to_vec()),
-            dependencies: BufferEntry::dependencies_in(id),
+            dependencies,
             state: State::NotReady,
+            switch,
+            is_padding,
+            received_at: None,
         })
     }
 }
 
+/// Writes a varint in reverse order, to be read from the end of the buffer.
+/// The maximum length is 10 bytes.
+fn put_reversed_var(buf: &mut BytesMut, value: u64) {
+    let mut tmp = [0u8; 10];
+    let len = value.encode_var(&mut tmp);
+    tmp[..len].reverse();
+    buf.put(&tmp[..len]);
+}
+
+/// Start of a field of `len` bytes ending at `end`.
+/// Returns `Decoding` if the field starts before the buffer.
+fn checked_start(end: usize, len: u64) -> Result<usize> {
+    usize::try_from(len).ok().and_then(|len| end.checked_sub(len)).ok_or(Error::Decoding)
+}
+
+/// Reads a varint encoded in reverse order in the last bytes of the buffer.
+/// The maximum length is 10 bytes.
+/// Returns the value and the length of its encoding.
+fn get_reversed_var(buf: &[u8]) -> Result<(u64, usize)> {
+    let tmp: Vec<u8> = buf.iter().rev().take(10).copied().collect();
+    u64::decode_var(&tmp[..]).ok_or(Error::Decoding)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hash::{Blake3, Sha256, Sha256Trunc80, Sha384};
+    use crate::scheme::Scheme;
 
-    #[test]
-    fn test_bytes() {
-        for do_sign in [true, false] {
+    fn check_bytes<H: PacketHasher + PartialEq>(format: WireFormat) {
+        let signatures = [
+            None,
+            Some(Signature {
+                algorithm: SignatureAlgorithm::Ed25519,
+                bytes: vec![77; 64],
+            }),
+            Some(Signature {
+                algorithm: SignatureAlgorithm::RsaPss,
+                bytes: vec![78; 384],
+            }),
+            Some(Signature {
+                algorithm: SignatureAlgorithm::MlDsa65,
+                bytes: vec![79; 3309],
+            }),
+        ];
+
+        let switches = [None, Some(Switch { start: 75, a: 2, p: 7 })];
+        let graph = Graph::new(Scheme::default());
+
+        let cases = signatures.into_iter().flat_map(|s| switches.map(|w| (s.clone(), w)));
+        for ((signature, switch), is_padding) in cases.flat_map(|c| [(c.clone(), false), (c, true)]) {
             let id = 56;
-            let dependencies = BufferEntry::dependencies_in(id);
-    
+            let dependencies = graph.dependencies_in(id);
+
             let mut hashes = VecDeque::new();
             for &i in dependencies.iter() {
-                hashes.push_back([i as u8; 32]);
+                let hash = vec![i as u8; H::DIGEST_LEN];
+                hashes.push_back(hash[..].try_into().ok().unwrap());
             }
-    
-            // Encode a packet with its payload.
-            let payload = vec![id as u8 * 2; id as usize];
+
+            // Encode a packet with its payload, empty for padding.
+            let payload = vec![id as u8 * 2; if is_padding { 0 } else { id as usize }];
             let mut buffer = [0; 1500];
             buffer[..payload.len()].copy_from_slice(&payload[..]);
 
-            let signature = if do_sign {
-                Some([77; 64])
-            } else {
-                None
-            };
-    
-            let mut entry = BufferEntry {
+            let mut entry = BufferEntry::<H> {
                 id,
                 hashes,
                 signature,
                 payload: None,
                 dependencies,
                 state: State::NotReady,
+                switch,
+                is_padding,
+                received_at: None,
             };
-    
+
             let mut buf = BytesMut::from(&buffer[..payload.len()]);
-            entry.encode(&mut buf);
-    
+            entry.encode_with(&mut buf, &graph, format);
+
             // Now we update the entry to match the decoded value by adding the payload.
             entry.payload = Some(payload);
-    
+
             let buf = buf.freeze();
-            let decoded_entry = BufferEntry::decode(buf).unwrap();
-    
+            let decoded_entry = BufferEntry::decode_with(buf, &graph, format).unwrap();
+
             assert_eq!(entry, decoded_entry);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_bytes() {
+        let compact = [WireFormat::Compact(IdWidth::Bits16), WireFormat::Compact(IdWidth::Bits32)];
+        for format in [WireFormat::Versioned, WireFormat::Legacy].into_iter().chain(compact) {
+            check_bytes::<Sha256>(format);
+            check_bytes::<Sha256Trunc80>(format);
+            check_bytes::<Sha384>(format);
+            check_bytes::<Blake3>(format);
+        }
+    }
+
+    #[test]
+    fn test_id_width() {
+        let width = IdWidth::Bits16;
+        assert_eq!(width.expand(10, 0), 10);
+        assert_eq!(width.expand(65530, 0), u64::MAX - 5);
+        assert_eq!(width.expand(2, 65530), 65538);
+        assert_eq!(width.expand(65530, 65538), 65530);
+        assert_eq!(width.expand(65530, 3 * 65536 + 2), 2 * 65536 + 65530);
+        assert_eq!(width.expand(7, u64::MAX), 7);
+        assert_eq!(width.expand(65530, 3), u64::MAX - 5);
+        assert_eq!(IdWidth::Bits32.expand(3, (1 << 32) - 1), (1 << 32) + 3);
+
+        // A stream starting near the wrap-around of the 16-bit IDs.
+        let graph = Graph::with_start(Scheme::default(), 65530);
+        let id = 65540;
+        let mut entry: BufferEntry = BufferEntry::dummy(id);
+        for &i in graph.dependencies_in(id).iter() {
+            entry.hashes.push_back([i as u8; 32]);
+        }
+        entry.dependencies = graph.dependencies_in(id);
+        let mut buf = BytesMut::from(entry.payload().unwrap());
+        entry.encode_with(&mut buf, &graph, WireFormat::Compact(width));
+        let buf = buf.freeze();
+
+        let trailer = Trailer::decode(&buf).unwrap();
+        assert_eq!(trailer.id_width, Some(width));
+        for expected_id in [65530, 65545, 65600] {
+            let decoded = BufferEntry::<Sha256>::decode_near(buf.clone(), &graph, WireFormat::Compact(width), expected_id);
+            assert_eq!(decoded.map(|node| node.id()), Ok(id));
+        }
+
+        // The truncated ID is never expanded before the start of the stream.
+        assert_eq!(
+            BufferEntry::<Sha256>::decode_near(buf, &graph, WireFormat::Compact(width), 0).map(|node| node.id()),
+            Ok(id)
+        );
+
+        // Signed nodes carry their full ID, to anchor the receivers joining late.
+        entry.signature = Some(Signature {
+            algorithm: SignatureAlgorithm::Ed25519,
+            bytes: vec![77; 64],
+        });
+        let mut buf = BytesMut::from(entry.payload().unwrap());
+        entry.encode_with(&mut buf, &graph, WireFormat::Compact(width));
+        let buf = buf.freeze();
+        assert_eq!(Trailer::decode(&buf).unwrap().id_width, None);
+        let decoded = BufferEntry::<Sha256>::decode_near(buf, &graph, WireFormat::Compact(width), 1 << 40);
+        assert_eq!(decoded.map(|node| node.id()), Ok(id));
+    }
+
+    /// Encodes node 56 of the default scheme, signed.
+    fn encoded(graph: &Graph, format: WireFormat) -> Bytes {
+        let mut entry: BufferEntry = BufferEntry::dummy(56);
+        for &i in graph.dependencies_in(56).iter() {
+            entry.hashes.push_back([i as u8; 32]);
+        }
+        entry.signature = Some(Signature {
+            algorithm: SignatureAlgorithm::Ed25519,
+            bytes: vec![77; 64],
+        });
+        let mut buf = BytesMut::from(entry.payload().unwrap());
+        entry.encode_with(&mut buf, graph, format);
+        buf.freeze()
+    }
+
+    #[test]
+    fn test_bytes_malformed() {
+        let graph = Graph::new(Scheme::default());
+
+        // Truncated packets never panic.
+        for format in [WireFormat::Versioned, WireFormat::Legacy] {
+            let buf = encoded(&graph, format);
+            for len in 0..buf.len() {
+                let _ = BufferEntry::<Sha256>::decode_with(buf.slice(len..), &graph, format);
+                let _ = BufferEntry::<Sha256>::decode_with(buf.slice(..len), &graph, format);
+            }
+            assert_eq!(BufferEntry::<Sha256>::decode_with(Bytes::new(), &graph, format), Err(Error::Decoding));
+        }
+
+        // The varints never end.
+        let buf = Bytes::from(vec![0x80; 20]);
+        assert_eq!(BufferEntry::<Sha256>::decode_with(buf, &graph, WireFormat::Legacy), Err(Error::Decoding));
+
+        // The ALTA fields are longer than the packet.
+        for bytes_len in [64u64, 1 << 40, u64::MAX >> 8] {
+            let mut tmp = bytes_len.encode_var_vec();
+            tmp.reverse();
+            let mut buf = vec![1u8; 10];
+            buf.extend(tmp);
+            buf.push(1);
+            let buf = Bytes::from(buf);
+            assert_eq!(BufferEntry::<Sha256>::decode_with(buf, &graph, WireFormat::Legacy), Err(Error::Decoding));
+        }
+
+        // Varints of any value are read back, but IDs preceding the start are rejected.
+        for value in [0, 1 << 60, 1 << 63, u64::MAX] {
+            let mut buf = BytesMut::from(&[1u8; 3][..]);
+            put_reversed_var(&mut buf, value);
+            assert_eq!(get_reversed_var(&buf), Ok((value, value.required_space())));
+        }
+        for format in [WireFormat::Versioned, WireFormat::Legacy] {
+            for id in [1 << 63, u64::MAX] {
+                let entry: BufferEntry = BufferEntry::dummy(id);
+                let mut buf = BytesMut::from(entry.payload().unwrap());
+                entry.encode_with(&mut buf, &graph, format);
+                assert_eq!(BufferEntry::<Sha256>::decode_with(buf.freeze(), &graph, format), Err(Error::Decoding));
+            }
+        }
+
+        // Padding nodes with a payload.
+        let mut buf = BytesMut::from(&encoded(&graph, WireFormat::Versioned)[..]);
+        let len = buf.len();
+        buf[len - 2] |= FLAG_PADDING;
+        assert_eq!(BufferEntry::<Sha256>::decode(buf.freeze(), &graph), Err(Error::Decoding));
+
+        // Truncated scheme switch.
+        let mut buf = BytesMut::from(&[1u8; 10][..]);
+        buf.put(&[LEGACY_SWITCH_TAG, 0, 0][..]);
+        buf.put_u8(3);
+        buf.put_u8(3);
+        let buf = buf.freeze();
+        assert_eq!(BufferEntry::<Sha256>::decode_with(buf, &graph, WireFormat::Legacy), Err(Error::Decoding));
+    }
+
+    #[test]
+    fn test_trailer() {
+        let graph = Graph::new(Scheme::default());
+        let buf = encoded(&graph, WireFormat::Versioned);
+
+        let trailer = Trailer::decode(&buf).unwrap();
+        assert_eq!(
+            trailer,
+            Trailer {
+                version: WIRE_VERSION,
+                hash_algorithm: Sha256::ID,
+                signature_algorithm: Some(SignatureAlgorithm::Ed25519),
+                a: 3,
+                p: 5,
+                nb_hashes: 2,
+                has_switch: false,
+                id_width: None,
+                is_padding: false,
+            }
+        );
+
+        // Receivers reject newer versions and other algorithms.
+        let mut newer = BytesMut::from(&buf[..]);
+        *newer.last_mut().unwrap() = WIRE_VERSION + 1;
+        assert_eq!(BufferEntry::<Sha256>::decode(newer.freeze(), &graph), Err(Error::Unsupported));
+        assert_eq!(BufferEntry::<Blake3>::decode(buf.clone(), &graph), Err(Error::Unsupported));
+        let mut unknown = BytesMut::from(&buf[..]);
+        let len = unknown.len();
+        unknown[len - 3] = 42;
+        assert_eq!(BufferEntry::<Sha256>::decode(unknown.freeze(), &graph), Err(Error::Unsupported));
+
+        // Receivers following another scheme reject the packets.
+        let other = Graph::new(Scheme::new(2, 7).unwrap());
+        assert_eq!(BufferEntry::<Sha256>::decode(buf.clone(), &other), Err(Error::InvalidScheme));
+
+        // Legacy packets are only decoded with the explicit option.
+        let legacy = encoded(&graph, WireFormat::Legacy);
+        assert!(BufferEntry::<Sha256>::decode(legacy.clone(), &graph).is_err());
+        assert!(BufferEntry::<Sha256>::decode_with(legacy, &graph, WireFormat::Legacy).is_ok());
+    }
+}
@@ -0,0 +1,109 @@
+//! Graphviz rendering of the window of a buffer, to follow which losses break which chains.
+
+use std::fmt::Write;
+
+use super::Buffer;
+use crate::hash::PacketHasher;
+use crate::State;
+
+/// Fill colour of a node in the given state.
+fn color(state: State) -> &'static str {
+    match state {
+        State::NotReady => "lightgrey",
+        State::ReadySent => "lightblue",
+        State::Authenticated => "palegreen",
+        State::BadAuthentication => "salmon",
+    }
+}
+
+impl<H: PacketHasher> Buffer<H> {
+    /// Renders the window of the buffer as a DOT graph, from its lowest ID to the highest ID buffered.
+    /// Nodes are coloured by state, signed nodes have a bold border, and missing nodes are dashed.
+    /// An edge goes from the node holding a hash to the node it authenticates, and each row of the
+    /// scheme is a column.
+    pub fn to_dot(&self) -> String {
+        let len = (0..self.capacity() as u64)
+            .filter(|&i| {
+                let id = self.lowest_id.wrapping_add(i);
+                self.buffer[index!(self, id)].as_ref().is_some_and(|e| e.id == id)
+            })
+            .max()
+            .map_or(0, |i| i + 1);
+        let ids = || (0..len).map(|i| self.lowest_id.wrapping_add(i));
+        let is_rendered = |id: u64| id.wrapping_sub(self.lowest_id) < len;
+
+        let mut out = String::from("digraph alta {\n    rankdir=LR;\n    node [shape=circle, style=filled];\n");
+        for id in ids() {
+            match self.buffer[index!(self, id)].as_ref().filter(|e| e.id == id) {
+                Some(entry) => {
+                    let border = if entry.signature.is_some() { ", penwidth=3" } else { "" };
+                    let _ = writeln!(out, "    {id} [fillcolor={}{border}];", color(entry.state));
+                },
+                None => {
+                    let _ = writeln!(out, "    {id} [style=dashed];");
+                },
+            }
+        }
+
+        for id in ids() {
+            if self.graph.is_row_head(id) || id == self.lowest_id {
+                let p = self.graph.scheme_at(id).p() as u64;
+                let row_len = p - id.wrapping_sub(self.graph.row_head(id));
+                let row: Vec<String> = (0..row_len)
+                    .map(|i| id.wrapping_add(i))
+                    .filter(|&id| is_rendered(id))
+                    .map(|id| id.to_string())
+                    .collect();
+                let _ = writeln!(out, "    {{ rank=same; {}; }}", row.join("; "));
+            }
+            let parents = self.graph.dependencies_out(id).into_iter();
+            for parent in parents.filter(|&parent| is_rendered(parent)) {
+                let _ = writeln!(out, "    {parent} -> {id};");
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+
+    use super::*;
+    use crate::buffer::recv_buf::RecvBuf;
+    use crate::buffer::BufferEntry;
+    use crate::scheme::Scheme;
+    use crate::sign::Signer;
+
+    #[test]
+    fn test_dot() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut sb: Buffer = Buffer::new(Scheme::default(), true);
+        let mut nodes: Vec<BufferEntry> = Vec::new();
+        let mut id = 0;
+        while nodes.len() < 15 {
+            id = sb.push_pkts(id);
+            sb.forw_hash();
+            nodes.extend(sb.pop_ready_in_sequence());
+        }
+        let hash = nodes[10].compute_total_hash();
+        nodes[10].signature = Some(key.sign(&hash));
+
+        // Node 12 is lost.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        for node in nodes.into_iter().take(15).filter(|node| node.id != 12) {
+            rb.insert(node).unwrap();
+        }
+
+        let dot = rb.to_dot();
+        assert!(dot.starts_with("digraph alta {"));
+        assert!(dot.contains("    10 [fillcolor=palegreen, penwidth=3];"));
+        assert!(dot.contains("    11 [fillcolor=palegreen];"));
+        assert!(dot.contains("    12 [style=dashed];"));
+        assert!(dot.contains("    { rank=same; 10; 11; 12; 13; 14; }"));
+        assert!(dot.contains("    10 -> 11;"));
+        assert!(!dot.contains("    15 "));
+    }
+}
@@ -1,28 +1,38 @@
 use std::collections::VecDeque;
 use std::fmt::Debug;
-use std::u64;
-
+use std::time::{Duration, Instant};
+
+use crate::feedback::LossStats;
+use crate::graph::{Graph, Switch};
+use crate::hash::{PacketHasher, Sha256};
+use crate::scheme::Scheme;
+use crate::sign::{Signer, Verifier};
+use observer::Observer;
+use recv_buf::{DeliveryMode, FloodStats, JoinPolicy, ReleasePolicy, Released, DEFAULT_MAX_CANDIDATES};
+use send_buf::{SignaturePolicy, SignatureSchedule};
 use crate::Error;
 use crate::Result;
 use crate::State;
-use crate::ALTA_A;
-use crate::ALTA_P;
 use crate::{PktHash, Signature};
 
-const BUFF_SIZE: usize = (ALTA_A * ALTA_P + 1) * 2;
+/// Domain separation tag prepended to the input of the node hash.
+const NODE_HASH_DOMAIN: &[u8] = b"ALTA-node-hash-v1";
 
+/// Index of the slot holding the node ID in the buffer.
+/// The slots follow the offset of the ID in the stream, so that they do not jump when the IDs wrap around.
 macro_rules! index {
-    ($s:expr) => {
-        $s as usize % BUFF_SIZE
+    ($b:expr, $s:expr) => {
+        ($b.graph.offset($s) % $b.buffer.len() as u64) as usize
     };
 }
 
 #[derive(PartialEq, Eq)]
 /// Internal representation of an element in the Buffer.
-pub struct BufferEntry {
+pub struct BufferEntry<H: PacketHasher = Sha256> {
     /// Ordered list of packet hashes.
-    /// Maximum number of hashes is 5 in mode a=3,p=5.
-    hashes: VecDeque<PktHash>,
+    /// One hash per dependency of the node, see `Graph::dependencies_in`, so the number of hashes
+    /// grows with the (a, p) scheme, and with the boundary of a scheme switch.
+    hashes: VecDeque<PktHash<H>>,
 
     /// Optional digital signature.
     signature: Option<Signature>,
@@ -38,21 +48,32 @@ pub struct BufferEntry {
 
     /// The state of the node.
     state: State,
+
+    /// Optional announcement of an upcoming scheme switch.
+    switch: Option<Switch>,
+
+    /// Whether the node only completes a row, without payload of the application.
+    is_padding: bool,
+
+    /// Time at which the node was inserted in the buffer.
+    received_at: Option<Instant>,
 }
 
-impl Debug for BufferEntry {
+impl<H: PacketHasher> Debug for BufferEntry<H> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("BufferEntry")
             .field("hashes", &self.hashes.len())
-            .field("signature", &self.signature)
+            .field("signature", &self.signature.as_ref().map(|s| s.algorithm))
             .field("id", &self.id)
             .field("dependencies", &self.dependencies)
             .field("state", &self.state)
+            .field("switch", &self.switch)
+            .field("is_padding", &self.is_padding)
             .finish()
     }
 }
 
-impl BufferEntry {
+impl<H: PacketHasher> BufferEntry<H> {
     /// New simple entry with an ID.
     pub fn new_id(id: u64) -> Self {
         Self {
@@ -60,71 +81,146 @@ impl BufferEntry {
             signature: None,
             id,
             payload: None,
-            dependencies: Self::dependencies_in(id),
+            dependencies: Vec::new(),
             state: State::NotReady,
+            switch: None,
+            is_padding: false,
+            received_at: None,
         }
     }
 
     /// New entry with a payload and an ID.
+    /// Its dependencies are set by the buffer, following its scheme, when the node is inserted.
     pub fn new(id: u64, payload: Vec<u8>) -> Self {
         let mut out = Self::new_id(id);
         out.payload = Some(payload);
         out
     }
 
-    /// Get the IDs of nodes that this node must send its hash to.
-    pub fn dependencies_out(&self) -> Vec<u64> {
-        let id: u64 = self.id;
-        match id % 5 {
-            0 => [id + 5, id + 15].to_vec(),
-            1 => [id - 1, id + 4].to_vec(),
-            2 => [id - 1, id + 2].to_vec(),
-            3 => [id - 1, id + 1].to_vec(),
-            4 => [id - 3, id + 1].to_vec(),
-            _ => Vec::new(),
-        }
-    }
-
-    /// Get the IDs of nodes that must send their hash to this ID.
-    pub fn dependencies_in(id: u64) -> Vec<u64> {
-        let out = match id % 5 {
-            0 => vec![-15i64, -5, -4, -1, 1],
-            1 => vec![1, 3],
-            2 => vec![1],
-            3 => Vec::new(),
-            4 => vec![-2, -1],
-            _ => Vec::new(),
-        };
-
-        let out = out
-            .iter()
-            .map(|&v| {
-                if v < 0 {
-                    id.checked_sub(-v as u64)
-                } else {
-                    id.checked_add(v as u64)
-                }
-            })
-            .flatten()
-            .collect();
+    /// New entry completing a row, without payload of the application.
+    /// The receivers authenticate it as any other node, but do not deliver it.
+    pub fn padding(id: u64) -> Self {
+        let mut out = Self::new(id, Vec::new());
+        out.is_padding = true;
         out
     }
 
+    /// The ID of the node.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
     /// The state of the node.
     pub fn state(&self) -> State {
         self.state
     }
 
+    /// The digital signature of the node, if any.
+    pub fn signature(&self) -> Option<&Signature> {
+        self.signature.as_ref()
+    }
+
+    /// The payload of the node, if any.
+    pub fn payload(&self) -> Option<&[u8]> {
+        self.payload.as_deref()
+    }
+
+    /// The scheme switch announced by the node, if any.
+    pub fn switch(&self) -> Option<Switch> {
+        self.switch
+    }
+
+    /// Whether the node only completes a row, see `BufferEntry::padding`.
+    pub fn is_padding(&self) -> bool {
+        self.is_padding
+    }
+
+    /// Time at which the node was inserted in the buffer.
+    pub fn received_at(&self) -> Option<Instant> {
+        self.received_at
+    }
+
+    /// Time elapsed since the insertion of the node in the buffer.
+    fn waited(&self) -> Duration {
+        self.received_at.map_or(Duration::ZERO, |received_at| received_at.elapsed())
+    }
+
+    /// Consumes the node and returns its payload, if any.
+    pub fn into_payload(self) -> Option<Vec<u8>> {
+        self.payload
+    }
+
+    /// Copy of the node without its payload and signature.
+    fn without_payload(&self) -> Self {
+        Self {
+            hashes: self.hashes.clone(),
+            signature: None,
+            id: self.id,
+            payload: None,
+            dependencies: self.dependencies.clone(),
+            state: self.state,
+            switch: self.switch,
+            is_padding: self.is_padding,
+            received_at: self.received_at,
+        }
+    }
+
+    /// Whether the other entry is a copy of this node.
+    /// Only the hashes, the scheme switch and the padding are compared once the payload is delivered.
+    fn is_copy_of(&self, other: &Self) -> bool {
+        self.hashes == other.hashes
+            && self.switch == other.switch
+            && self.is_padding == other.is_padding
+            && (self.payload.is_none() || (self.payload == other.payload && self.signature == other.signature))
+    }
+
     /// Computes the hash of the packet with its children hashes.
-    pub fn compute_total_hash(&self) -> [u8; 32] {
-        // TODO.
-        [0u8; 32]
+    ///
+    /// The digest is computed with `H` over the following layout, all integers in big-endian:
+    ///
+    /// ```text
+    /// "ALTA-node-hash-v1" | id (u64) | nb hashes (u16) | hashes (H::DIGEST_LEN bytes each, in order)
+    ///                     | payload length (u64) | payload [| switch start (u64) | a (u16) | p (u16)]
+    ///                     [| padding (u8, 1)]
+    /// ```
+    ///
+    /// A missing payload is hashed as an empty one.
+    /// The scheme switch is only hashed if the node announces one, and the padding byte if the node is padding.
+    /// The signature is not part of the hash since it is computed over it.
+    pub fn compute_total_hash(&self) -> PktHash<H> {
+        let payload = self.payload.as_deref().unwrap_or(&[]);
+        let id = self.id.to_be_bytes();
+        let nb_hashes = (self.hashes.len() as u16).to_be_bytes();
+        let payload_len = (payload.len() as u64).to_be_bytes();
+        let switch = self.switch.map(|switch| {
+            let mut out = [0u8; 12];
+            out[..8].copy_from_slice(&switch.start.to_be_bytes());
+            out[8..10].copy_from_slice(&(switch.a as u16).to_be_bytes());
+            out[10..].copy_from_slice(&(switch.p as u16).to_be_bytes());
+            out
+        });
+
+        let mut chunks: Vec<&[u8]> = Vec::with_capacity(self.hashes.len() + 6);
+        chunks.push(NODE_HASH_DOMAIN);
+        chunks.push(&id);
+        chunks.push(&nb_hashes);
+        chunks.extend(self.hashes.iter().map(|hash| hash.as_ref()));
+        chunks.push(&payload_len);
+        chunks.push(payload);
+        if let Some(switch) = switch.as_ref() {
+            chunks.push(switch);
+        }
+        if self.is_padding {
+            chunks.push(&[1]);
+        }
+
+        H::digest(&chunks)
     }
 
     /// Compare the children hashes with an external hash. Try to find a match.
     /// Currently iterates over all hashes.
     /// Returns an error if the current node is not itself authenticated.
-    pub fn compare_hash(&self, hash: &[u8; 32]) -> Result<()> {
+    pub fn compare_hash(&self, hash: &PktHash<H>) -> Result<()> {
         if self.state != State::Authenticated {
             return Err(Error::NotAuthenticated);
         }
@@ -140,11 +236,14 @@ impl BufferEntry {
 }
 
 /// Buffer containing all hashes that need to be buffered.
-/// Specific for the a=3,p=5 case.
-pub struct Buffer {
+pub struct Buffer<H: PacketHasher = Sha256> {
     /// Data.
-    /// The maximum number of hashes that must be buffered is p(a - 1) = 10.
-    buffer: Vec<Option<BufferEntry>>,
+    /// Holds at least `(a * p + 1) * 2` nodes, following the largest scheme of the graph.
+    /// The receive buffer may grow further up to its maximum capacity.
+    buffer: Vec<Option<BufferEntry<H>>>,
+
+    /// Shape of the dependency graph.
+    graph: Graph,
 
     /// Lowest ID buffered.
     lowest_id: u64,
@@ -157,34 +256,201 @@ pub struct Buffer {
 
     /// Whether the buffer waits for symbols to be ready (send buffer) or authenticated (receive buffer) to pop packets.
     state_to_pop: State,
+
+    /// Signs the total hash of nodes (send buffer).
+    signer: Option<Box<dyn Signer>>,
+
+    /// Decides which nodes are signed (send buffer).
+    signature_schedule: SignatureSchedule,
+
+    /// Verifies the digital signatures of the nodes (receive buffer).
+    verifier: Option<Box<dyn Verifier>>,
+
+    /// Scheme switch announced in the nodes inserted until it applies (send buffer).
+    pending_switch: Option<Switch>,
+
+    /// Loss and authentication counters (receive buffer).
+    stats: LossStats,
+
+    /// Decides when nodes that are not authenticated leave the buffer (receive buffer).
+    release_policy: ReleasePolicy,
+
+    /// Nodes released to make room for a new node, not popped yet (receive buffer).
+    released: VecDeque<Released<H>>,
+
+    /// Maximum time a node waits for its authentication (receive buffer).
+    deadline: Option<Duration>,
+
+    /// Number of nodes the buffer may grow to, to receive nodes after the end of the window (receive buffer).
+    max_capacity: usize,
+
+    /// Decides when the authenticated nodes are delivered (receive buffer).
+    delivery_mode: DeliveryMode,
+
+    /// Nodes authenticated and delivered immediately, not popped yet (receive buffer).
+    delivered: VecDeque<BufferEntry<H>>,
+
+    /// Other versions of the buffered nodes, kept until one version is authenticated (receive buffer).
+    candidates: Vec<BufferEntry<H>>,
+
+    /// Maximum number of other versions kept for a buffered node (receive buffer).
+    max_candidates: usize,
+
+    /// Counters of the packets suspected to be forged (receive buffer).
+    flood: FloodStats,
+
+    /// Decides where the window starts (receive buffer).
+    join_policy: JoinPolicy,
+
+    /// Whether the window started with the first node inserted (receive buffer).
+    joined: bool,
+
+    /// Highest ID authenticated, from which the loss horizon is counted (receive buffer).
+    highest_authenticated: Option<u64>,
+
+    /// Nodes received after the end of the window, kept until the window reaches them (receive buffer).
+    parked: VecDeque<BufferEntry<H>>,
+
+    /// Whether the window started at a node that may be forged, and no node is authenticated yet,
+    /// with `JoinPolicy::FirstPacket` (receive buffer).
+    is_provisional: bool,
+
+    /// Observer of the events of the buffer.
+    observer: Option<Box<dyn Observer>>,
 }
 
-impl Buffer {
+impl<H: PacketHasher> Buffer<H> {
     /// Creates a new, empty buffer.
-    fn new(is_send: bool) -> Self {
+    fn new(scheme: Scheme, is_send: bool) -> Self {
+        let capacity = scheme.capacity();
         Self {
-            buffer: (0..BUFF_SIZE).map(|_| None).collect(),
+            buffer: (0..capacity).map(|_| None).collect(),
             lowest_id: 0,
-            latest_id: 0,
-            next_node_id_hash: 3,
+            latest_id: u64::MAX,
+            next_node_id_hash: scheme.first_node_id_hash(),
+            graph: Graph::new(scheme),
             state_to_pop: if is_send {
                 State::ReadySent
             } else {
                 State::Authenticated
             },
+            signer: None,
+            signature_schedule: SignatureSchedule::new(SignaturePolicy::EndOfBlock),
+            verifier: None,
+            pending_switch: None,
+            stats: LossStats::default(),
+            release_policy: ReleasePolicy::default(),
+            released: VecDeque::new(),
+            deadline: None,
+            max_capacity: capacity,
+            delivery_mode: DeliveryMode::default(),
+            delivered: VecDeque::new(),
+            candidates: Vec::new(),
+            max_candidates: DEFAULT_MAX_CANDIDATES,
+            flood: FloodStats::default(),
+            join_policy: JoinPolicy::default(),
+            joined: false,
+            highest_authenticated: None,
+            parked: VecDeque::new(),
+            is_provisional: false,
+            observer: None,
+        }
+    }
+
+    /// The scheme of the latest nodes of the dependency graph.
+    pub fn scheme(&self) -> &Scheme {
+        self.graph.last_scheme()
+    }
+
+    /// The dependency graph, with all the scheme switches.
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// Adds the scheme switch to the graph.
+    /// The buffer grows if the new scheme needs more capacity, and never shrinks.
+    fn apply_switch(&mut self, switch: Switch) -> Result<()> {
+        self.graph.switch(switch)?;
+        self.grow(self.graph.capacity());
+        self.max_capacity = self.max_capacity.max(self.capacity());
+
+        // The nodes around the switch may have been buffered before it was known.
+        for entry in self.buffer.iter_mut().flatten().chain(self.candidates.iter_mut()) {
+            entry.dependencies = self.graph.dependencies_in(entry.id);
+        }
+
+        Ok(())
+    }
+
+    /// Grows the buffer to hold at least the capacity.
+    fn grow(&mut self, capacity: usize) {
+        if capacity > self.buffer.len() {
+            let old = std::mem::replace(&mut self.buffer, (0..capacity).map(|_| None).collect());
+            for entry in old.into_iter().flatten() {
+                // Keep the most recent node if two nodes share the same slot.
+                let idx = index!(self, entry.id);
+                if self.buffer[idx].as_ref().is_none_or(|e| self.graph.offset(e.id) < self.graph.offset(entry.id)) {
+                    self.buffer[idx] = Some(entry);
+                }
+            }
         }
     }
 
+    /// Starts the stream at the ID instead of 0, e.g., at a random initial ID chosen by the sender
+    /// to avoid the confusion with the packets of a previous session.
+    /// Must be called before inserting any node.
+    pub fn set_initial_id(&mut self, id: u64) {
+        self.graph = Graph::with_start(self.graph.last_scheme().clone(), id);
+        self.lowest_id = id;
+        self.latest_id = id.wrapping_sub(1);
+        self.next_node_id_hash = self.graph.first_node_id_hash();
+    }
+
+    /// Sets the observer of the events of the buffer.
+    pub fn set_observer(&mut self, observer: Box<dyn Observer>) {
+        self.observer = Some(observer);
+    }
+
+    /// Calls the observer, if any.
+    fn observe(&mut self, event: impl FnOnce(&mut dyn Observer)) {
+        if let Some(observer) = self.observer.as_deref_mut() {
+            event(observer);
+        }
+    }
+
+    /// The ID expected next: the one after the highest ID received by the receive buffer,
+    /// or the first ID of the window.
+    pub fn expected_id(&self) -> u64 {
+        let next = self.stats.highest_id().map_or(self.lowest_id, |highest| highest.wrapping_add(1));
+        if self.graph.offset(next) > self.graph.offset(self.lowest_id) {
+            next
+        } else {
+            self.lowest_id
+        }
+    }
+
+    /// Number of nodes the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Whether the ID is inside the window of the buffer.
+    fn in_window(&self, id: u64) -> bool {
+        id.wrapping_sub(self.lowest_id) < self.capacity() as u64
+    }
+
     /// Returns the entry if it exists, or create it and returns a mutable reference to it.
-    fn get_or_create(&mut self, id: u64) -> Result<&mut BufferEntry> {
-        if id < self.lowest_id || id >= self.lowest_id + BUFF_SIZE as u64 {
+    fn get_or_create(&mut self, id: u64) -> Result<&mut BufferEntry<H>> {
+        if !self.in_window(id) {
             return Err(Error::OutOfBoundId);
         }
 
-        let index = index!(id);
+        let index = index!(self, id);
         let entry = &self.buffer[index];
         if entry.as_ref().is_some_and(|e| e.id != id) || entry.is_none() {
-            self.buffer[index] = Some(BufferEntry::new_id(id));
+            let mut entry = BufferEntry::new_id(id);
+            entry.dependencies = self.graph.dependencies_in(id);
+            self.buffer[index] = Some(entry);
         }
 
         Ok(self.buffer[index].as_mut().unwrap())
@@ -193,35 +459,39 @@ impl Buffer {
     /// Returns the next node ID to process to forward packet hashes.
     pub fn next_node_id_hash(&mut self) -> u64 {
         let id = self.next_node_id_hash;
-
-        self.next_node_id_hash = match id % 5 {
-            0 => id + 8,
-            1 => id.saturating_sub(1),
-            2 => id + 2,
-            3 => id.saturating_sub(1),
-            4 => id.saturating_sub(3),
-            _ => id,
-        };
-
+        self.next_node_id_hash = self.graph.next_node_id_hash(id);
         id
     }
 
     /// Pop ready symbols from the buffer in sequence.
-    pub fn pop_ready_in_sequence(&mut self) -> Vec<BufferEntry> {
+    /// With `DeliveryMode::Immediate`, the receive buffer already delivered their payload.
+    /// The receive buffer keeps a copy of the popped nodes without their payload and signature,
+    /// so that their hashes still authenticate the children received after them.
+    pub fn pop_ready_in_sequence(&mut self) -> Vec<BufferEntry<H>> {
         let mut out = Vec::with_capacity(3);
 
         // Loop at most until we reach the end of the buffer size.
-        for _ in 0..BUFF_SIZE {
-            let index = index!(self.lowest_id);
+        for _ in 0..self.capacity() {
+            let index = index!(self, self.lowest_id);
 
             let entry = self.buffer[index].as_mut();
             if entry.is_some_and(|entry| entry.id == self.lowest_id && entry.state == self.state_to_pop) {
-                out.push(self.buffer[index].take().unwrap());
+                let entry = self.buffer[index].take().unwrap();
+
+                // The receive buffer keeps the hashes of the popped node until its slot is reused,
+                // since they may still authenticate children arriving later, e.g., the next node in its row.
+                if self.state_to_pop == State::Authenticated {
+                    self.buffer[index] = Some(entry.without_payload());
+                }
+
+                let waited = entry.waited();
+                self.observe(|o| o.on_pop(entry.id, waited));
+                out.push(entry);
             } else {
                 break;
             }
 
-            self.lowest_id += 1;
+            self.lowest_id = self.lowest_id.wrapping_add(1);
         }
 
         out
@@ -232,7 +502,7 @@ impl Buffer {
 mod testing {
     use super::*;
 
-    impl BufferEntry {
+    impl<H: PacketHasher> BufferEntry<H> {
         pub fn dummy(id: u64) -> Self {
             let payload = vec![42u8; 20];
             Self::new(id, payload)
@@ -240,6 +510,61 @@ mod testing {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_hex(hash: &PktHash) -> String {
+        hash.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_total_hash_vectors() {
+        // Empty node without payload.
+        let entry: BufferEntry = BufferEntry::new_id(0);
+        assert_eq!(
+            to_hex(&entry.compute_total_hash()),
+            "67058bb13fccd63299215bd3062aba41c70919b5f3b89212c82a406e725a1e35"
+        );
+
+        // Node without in-hashes.
+        let entry: BufferEntry = BufferEntry::dummy(3);
+        assert_eq!(
+            to_hex(&entry.compute_total_hash()),
+            "7daae1738f8bd028393593d09ac369705efb0e30800960e1af2bd8c995a51c71"
+        );
+
+        // Node with in-hashes.
+        let mut entry: BufferEntry = BufferEntry::dummy(5);
+        for &i in Scheme::default().dependencies_in(5).iter() {
+            entry.hashes.push_back([i as u8; 32]);
+        }
+        assert_eq!(
+            to_hex(&entry.compute_total_hash()),
+            "6d001f5a1712916375ac2b1fc8d57f32c767741c55baa8c1237a8e1d7e4ffc3a"
+        );
+
+        // The order of the in-hashes matters.
+        let reference = entry.compute_total_hash();
+        entry.hashes.swap(0, 1);
+        assert_ne!(entry.compute_total_hash(), reference);
+
+        // The signature is not part of the hash.
+        entry.hashes.swap(0, 1);
+        entry.signature = Some(Signature {
+            algorithm: crate::sign::SignatureAlgorithm::Ed25519,
+            bytes: vec![1; 64],
+        });
+        assert_eq!(entry.compute_total_hash(), reference);
+
+        // Padding differs from an empty payload.
+        let padding: BufferEntry = BufferEntry::padding(3);
+        assert_ne!(padding.compute_total_hash(), BufferEntry::<Sha256>::new(3, Vec::new()).compute_total_hash());
+    }
+}
+
 pub mod recv_buf;
 pub mod send_buf;
 pub mod bytes;
+pub mod dot;
+pub mod observer;
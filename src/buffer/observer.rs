@@ -0,0 +1,150 @@
+//! Hooks following the nodes through the buffers, e.g., for monitoring.
+
+use std::time::Duration;
+
+use crate::Error;
+
+/// How a node was authenticated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// With its digital signature.
+    Signature,
+
+    /// With the hash held by the authenticated parent with this ID.
+    Parent(u64),
+}
+
+/// Why a node left the receive buffer without being authenticated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// The node cannot be authenticated anymore, following the release policy.
+    Unverified,
+
+    /// The node was not authenticated before the deadline.
+    Expired,
+
+    /// The node was never received.
+    Lost,
+
+    /// Another version of the node was authenticated, this version cannot be authenticated,
+    /// or it was evicted by a newer version.
+    Forged,
+}
+
+/// Observer of the events of a buffer, set with `Buffer::set_observer`.
+/// The durations are measured from the insertion of the node in the buffer.
+/// All the methods do nothing by default.
+pub trait Observer: Send {
+    /// The node is inserted in the buffer.
+    fn on_insert(&mut self, _id: u64) {}
+
+    /// The send buffer forwarded the hash of the node to its children, and signed it if required.
+    fn on_forward(&mut self, _id: u64, _is_signed: bool, _waited: Duration) {}
+
+    /// The receive buffer authenticated the node.
+    fn on_authenticate(&mut self, _id: u64, _method: AuthMethod, _waited: Duration) {}
+
+    /// The receive buffer failed to authenticate the node.
+    fn on_failure(&mut self, _id: u64, _error: Error) {}
+
+    /// The node, or a version of it, left the receive buffer without being authenticated.
+    fn on_evict(&mut self, _id: u64, _reason: Eviction) {}
+
+    /// The node left the buffer in sequence, ready to be sent or authenticated.
+    fn on_pop(&mut self, _id: u64, _waited: Duration) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use ed25519_dalek::SigningKey;
+
+    use super::*;
+    use crate::buffer::recv_buf::RecvBuf;
+    use crate::buffer::send_buf::SendBuffer;
+    use crate::buffer::{Buffer, BufferEntry};
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Event {
+        Insert(u64),
+        Forward(u64, bool),
+        Authenticate(u64, AuthMethod),
+        Evict(u64, Eviction),
+        Pop(u64),
+    }
+
+    /// Records the events in a shared list.
+    struct Recorder(Arc<Mutex<Vec<Event>>>);
+
+    impl Observer for Recorder {
+        fn on_insert(&mut self, id: u64) {
+            self.0.lock().unwrap().push(Event::Insert(id));
+        }
+
+        fn on_forward(&mut self, id: u64, is_signed: bool, _waited: Duration) {
+            self.0.lock().unwrap().push(Event::Forward(id, is_signed));
+        }
+
+        fn on_authenticate(&mut self, id: u64, method: AuthMethod, _waited: Duration) {
+            self.0.lock().unwrap().push(Event::Authenticate(id, method));
+        }
+
+        fn on_evict(&mut self, id: u64, reason: Eviction) {
+            self.0.lock().unwrap().push(Event::Evict(id, reason));
+        }
+
+        fn on_pop(&mut self, id: u64, _waited: Duration) {
+            self.0.lock().unwrap().push(Event::Pop(id));
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let mut sb: Buffer = SendBuffer::new(Box::new(key.clone()));
+        sb.set_observer(Box::new(Recorder(events.clone())));
+        let mut nodes = Vec::new();
+        for id in 0..40 {
+            sb.insert_in_sequence(BufferEntry::new(id, vec![id as u8; 10])).unwrap();
+            sb.forward_ready();
+            nodes.extend(sb.pop_ready_in_sequence());
+        }
+
+        // The head of the row is forwarded last, and the row is popped once it is forwarded.
+        let sent = std::mem::take(&mut *events.lock().unwrap());
+        let first_row = [
+            Event::Insert(0),
+            Event::Insert(1),
+            Event::Insert(2),
+            Event::Insert(3),
+            Event::Forward(3, false),
+            Event::Forward(2, false),
+            Event::Insert(4),
+            Event::Forward(4, false),
+            Event::Forward(1, false),
+            Event::Forward(0, false),
+        ];
+        assert_eq!(sent[..10], first_row);
+        assert_eq!(sent[10..15], [0, 1, 2, 3, 4].map(Event::Pop));
+        assert!(sent.contains(&Event::Forward(10, true)));
+
+        // Node 7 is lost, and node 10 is signed.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_observer(Box::new(Recorder(events.clone())));
+        for node in nodes.into_iter().filter(|node| node.id() != 7) {
+            rb.insert(node).unwrap();
+            let _ = rb.pop_released();
+        }
+
+        let received = events.lock().unwrap();
+        assert_eq!(received[0], Event::Insert(0));
+        assert!(received.contains(&Event::Authenticate(10, AuthMethod::Signature)));
+        assert!(received.contains(&Event::Authenticate(11, AuthMethod::Parent(10))));
+        assert!(received.contains(&Event::Evict(7, Eviction::Lost)));
+        assert!(!received.iter().any(|e| matches!(e, Event::Insert(7) | Event::Pop(7))));
+        assert!(received.contains(&Event::Pop(8)));
+    }
+}
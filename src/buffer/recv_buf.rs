@@ -1,47 +1,323 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+use super::bytes::{Trailer, WireFormat};
 use super::Buffer;
 use super::BufferEntry;
+use super::observer::{AuthMethod, Eviction};
+use crate::feedback::{LossStats, Report};
+use crate::graph::Switch;
+use crate::hash::PacketHasher;
+use crate::scheme::Scheme;
+use crate::sign::Verifier;
 use crate::Result;
 use crate::Error;
-use super::BUFF_SIZE;
 use super::State;
+use crate::hash::Sha256;
+
+/// Default number of IDs after which a missing node is considered lost.
+pub const DEFAULT_LOSS_HORIZON: u64 = 16;
+
+/// Default number of other versions kept for a buffered node.
+pub const DEFAULT_MAX_CANDIDATES: usize = 2;
+
+/// Counters of the packets suspected to be forged, since the creation of the buffer.
+/// A high number of conflicts hints that an attacker floods the receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FloodStats {
+    /// Number of packets differing from another version of their node.
+    pub nb_conflicts: u64,
+
+    /// Number of versions discarded since they cannot be authenticated,
+    /// or since another version of their node was authenticated.
+    pub nb_forged: u64,
+
+    /// Number of versions evicted, or rejected if no candidate is allowed, since their node already
+    /// had the maximum number of candidates. The oldest candidate is evicted to keep the new version.
+    pub nb_rejected: u64,
+
+    /// Number of versions whose signature, or hash held by an authenticated parent, does not verify.
+    pub nb_bad_authentications: u64,
+
+    /// Number of nodes received after the end of the window whose signature does not verify.
+    /// They neither grow nor move the window, and wait for the window to reach them.
+    pub nb_out_of_window: u64,
+}
+
+/// Decides when the receive buffer gives up on the nodes that are not authenticated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReleasePolicy {
+    /// Nodes only leave the buffer once authenticated.
+    /// A single lost node may stall the buffer.
+    Blocking,
+
+    /// A missing node is considered lost once a node `n` IDs later is authenticated.
+    /// Nodes lost, or that cannot be authenticated anymore because all their parents are lost,
+    /// released or unverifiable, leave the buffer in sequence with the authenticated ones.
+    /// A node received after the end of the window whose signature verifies releases the first
+    /// nodes to make room. The other nodes after the end of the window wait until it moves.
+    LossHorizon(u64),
+}
+
+impl Default for ReleasePolicy {
+    fn default() -> Self {
+        Self::LossHorizon(DEFAULT_LOSS_HORIZON)
+    }
+}
+
+/// Decides when the authenticated nodes are delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeliveryMode {
+    /// Nodes are delivered in sequence.
+    #[default]
+    InSequence,
+
+    /// Nodes are delivered as soon as they are authenticated, in any order.
+    /// The buffer keeps the delivered nodes, without their payload, until they are released in
+    /// sequence, so that they authenticate their children and are not delivered twice.
+    Immediate,
+}
+
+/// Decides where the window of the receive buffer starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinPolicy {
+    /// The window starts at the initial ID of the stream.
+    #[default]
+    Start,
 
-pub trait RecvBuf {
-    /// Creates a new receive buffer.
-    fn new() -> Self;
+    /// The window starts at the row of the first node received, to join a stream mid-way.
+    /// Since the first node may be forged, the window moves to the row of a node out of the window
+    /// whose digital signature verifies, until a node is authenticated. The nodes buffered before
+    /// are discarded, even if the signed node only arrived late.
+    FirstPacket,
+
+    /// The window starts at the first node whose digital signature verifies, to join a stream
+    /// mid-way. The nodes received before are rejected.
+    FirstSigned,
+}
+
+/// Node leaving the receive buffer.
+#[derive(Debug)]
+pub enum Released<H: PacketHasher = Sha256> {
+    /// Authenticated node.
+    Authenticated(BufferEntry<H>),
+
+    /// Received node that cannot be authenticated.
+    Unverified(BufferEntry<H>),
+
+    /// Received node that was not authenticated before the deadline.
+    Expired(BufferEntry<H>),
+
+    /// ID of a node that was never received.
+    Dropped(u64),
+}
+
+impl<H: PacketHasher> Released<H> {
+    /// The ID of the node.
+    pub fn id(&self) -> u64 {
+        match self {
+            Self::Authenticated(entry) | Self::Unverified(entry) | Self::Expired(entry) => entry.id,
+            Self::Dropped(id) => *id,
+        }
+    }
+}
+
+pub trait RecvBuf<H: PacketHasher> {
+    /// Creates a new receive buffer with the default a=3,p=5 scheme.
+    /// The verifier holds the public key of the sender to verify the digital signatures of the nodes.
+    fn new(verifier: Box<dyn Verifier>) -> Self;
+
+    /// Creates a new receive buffer with the given scheme.
+    fn with_scheme(scheme: Scheme, verifier: Box<dyn Verifier>) -> Self;
 
     /// Inserts a node in the buffer.
-    /// Returns an error if the node exceeds the capacity of the buffer.
-    fn insert(&mut self, node: BufferEntry) -> Result<()>;
+    /// A scheme switch announced by the node only applies once the node is authenticated, so that
+    /// a forged announcement has no effect. Until then, the nodes following the switch are buffered
+    /// if decoded with `RecvBuf::decode`.
+    /// A node that differs from the version already buffered is kept as a candidate until one of
+    /// the versions is authenticated, and the others are discarded.
+    /// The first node inserted starts the window of the buffer, following the join policy.
+    /// Returns an error if the node exceeds the capacity of the buffer,
+    /// `InvalidScheme` if an authenticated switch conflicts with the known ones,
+    /// `ConflictingDuplicate` if another version of the node was received and this version is not
+    /// authenticated, in which case it may still be kept as a candidate and delivered later,
+    /// or `NotAuthenticated` if the buffer waits for a signed node to join the stream.
+    fn insert(&mut self, node: BufferEntry<H>) -> Result<()>;
+
+    /// Decodes a packet in the wire format, with the graph of the buffer.
+    /// A node following a switch announced by itself or by a buffered node, not authenticated yet,
+    /// is decoded with this switch.
+    fn decode(&self, packet: Bytes, format: WireFormat) -> Result<BufferEntry<H>>;
 
     /// Tries to authenticate the node, either using the (optional) digital signature,
     /// or using a parent node that has already been authenticated.
-    /// If the current node has been authenticated, recursively calls the function on children nodes.
+    /// If the current node has been authenticated, its children nodes are authenticated in turn, and so on.
+    /// Returns `BadAuthentication` if the node does not verify. The children that do not verify are
+    /// reported to the observer and counted in the flood stats, since they do not depend on the caller.
     fn authenticate_node(&mut self, id: u64) -> Result<()>;
+
+    /// Produces a report of the losses and authentications since the previous report,
+    /// to send back to the sender. The caller decides the reporting period.
+    fn report(&mut self) -> Report;
+
+    /// Sets the policy deciding when the nodes that are not authenticated leave the buffer.
+    /// The default policy is `ReleasePolicy::LossHorizon(DEFAULT_LOSS_HORIZON)`.
+    fn set_release_policy(&mut self, policy: ReleasePolicy);
+
+    /// Sets the maximum time a node waits for its authentication, to bound the latency.
+    /// Once the first node received at or after the lowest ID waited for longer than the deadline,
+    /// the nodes until this one are released, regardless of the release policy.
+    /// Nodes do not expire by default.
+    fn set_deadline(&mut self, deadline: Option<Duration>);
+
+    /// Sets where the window of the buffer starts, e.g., for a receiver subscribing to the stream
+    /// after its start. The receiver still follows the dependency graph from the initial ID of the
+    /// stream, and must know its scheme at the time of joining: the switches are only learnt from
+    /// the announcing nodes, so a receiver joining once a switch is not announced anymore cannot
+    /// follow the stream.
+    /// The default policy is `JoinPolicy::Start`.
+    fn set_join_policy(&mut self, policy: JoinPolicy);
+
+    /// Sets the number of nodes the buffer may grow to, so that it receives nodes after the end of
+    /// its window, e.g., under reordering, instead of releasing the first nodes or rejecting them.
+    /// The buffer doubles its capacity as needed, for nodes whose signature verifies, and never shrinks.
+    /// By default, the buffer holds `(a * p + 1) * 2` nodes and does not grow.
+    fn set_max_capacity(&mut self, max_capacity: usize);
+
+    /// Sets the maximum number of other versions kept for a buffered node.
+    /// A further version evicts the oldest candidate, or is rejected if no candidate is allowed.
+    /// The default is `DEFAULT_MAX_CANDIDATES`.
+    fn set_max_candidates(&mut self, max_candidates: usize);
+
+    /// Counters of the packets suspected to be forged.
+    fn flood_stats(&self) -> FloodStats;
+
+    /// Sets when the authenticated nodes are delivered by `RecvBuf::pop_released`.
+    /// The default mode is `DeliveryMode::InSequence`.
+    fn set_delivery_mode(&mut self, mode: DeliveryMode);
+
+    /// Pops the nodes in sequence, either authenticated or given up following the release policy
+    /// and the deadline.
+    /// With `DeliveryMode::Immediate`, the nodes authenticated since the last call come first,
+    /// in the order of their authentication, and are not popped again in sequence.
+    fn pop_released(&mut self) -> Vec<Released<H>>;
 }
 
-impl RecvBuf for Buffer {
-    fn new() -> Self {
-        Buffer::new(false)
+impl<H: PacketHasher> RecvBuf<H> for Buffer<H> {
+    fn new(verifier: Box<dyn Verifier>) -> Self {
+        Self::with_scheme(Scheme::default(), verifier)
+    }
+
+    fn with_scheme(scheme: Scheme, verifier: Box<dyn Verifier>) -> Self {
+        let mut buffer = Buffer::new(scheme, false);
+        buffer.verifier = Some(verifier);
+        buffer
     }
 
-    fn insert(&mut self, mut node: BufferEntry) -> Result<()> {
+    fn insert(&mut self, mut node: BufferEntry<H>) -> Result<()> {
         let id = node.id;
-        let idx = index!(id);
-        
-        if id < self.lowest_id || id >= self.lowest_id + BUFF_SIZE as u64 {
-            return Err(Error::OutOfBoundId);
+        if !self.joined {
+            self.join(&node)?;
+        }
+
+        // Grow the window to the node if the budget allows it, or make room for a node following
+        // a long burst of losses, at most one window ahead. Only a node whose signature verifies
+        // grows or moves the window, since forged nodes would exhaust the budget or release all the
+        // nodes of the window. The other nodes wait for the window to reach them.
+        let capacity = self.capacity() as u64;
+        let gap = id.wrapping_sub(self.lowest_id);
+        let can_grow = gap >= capacity && gap < self.max_capacity as u64;
+        let can_make_room =
+            matches!(self.release_policy, ReleasePolicy::LossHorizon(_)) && gap >= capacity && gap < 2 * capacity;
+        if can_grow || can_make_room {
+            if !self.verifies_signature(&node) {
+                self.flood.nb_out_of_window += 1;
+                if self.parked.len() >= self.capacity() {
+                    self.parked.pop_front();
+                }
+                self.parked.push_back(node);
+                return Ok(());
+            }
+            if can_grow {
+                self.grow((self.capacity() * 2).clamp(gap as usize + 1, self.max_capacity));
+            }
+            while !self.in_window(id) {
+                let released = self.release_lowest(false);
+                self.released.push_back(released);
+            }
+        }
+        self.insert_parked();
+
+        if !self.in_window(id) {
+            // The window may have started at a forged node, far from the stream.
+            if !self.is_provisional || !self.verifies_signature(&node) {
+                return Err(Error::OutOfBoundId);
+            }
+            self.rejoin(&node)?;
         }
+        let idx = index!(self, id);
 
         // Check whether the node is already present in the buffer.
-        let entry = self.buffer[idx].as_mut();
-        if entry.is_some_and(|e| e.id == id) {
-            return Ok(());
+        if let Some(entry) = self.buffer[idx].as_ref().filter(|e| e.id == id) {
+            let is_copy = entry.is_copy_of(&node) || self.candidates.iter().any(|c| c.id == id && c.is_copy_of(&node));
+            if is_copy {
+                return Ok(());
+            }
+
+            // An authenticated node cannot be replaced.
+            self.flood.nb_conflicts += 1;
+            if entry.state != State::Authenticated {
+                // The oldest candidate makes room for the new version, so that junk versions
+                // arriving first do not prevent the legitimate one from being authenticated.
+                let nb_candidates = self.candidates.iter().filter(|c| c.id == id).count();
+                if nb_candidates >= self.max_candidates {
+                    let oldest = self
+                        .candidates
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, c)| c.id == id)
+                        .min_by_key(|(_, c)| c.received_at)
+                        .map(|(pos, _)| pos);
+                    if let Some(pos) = oldest {
+                        self.candidates.remove(pos);
+                        self.flood.nb_rejected += 1;
+                        self.observe(|o| o.on_evict(id, Eviction::Forged));
+                    }
+                }
+                if self.max_candidates > 0 {
+                    let hash = node.compute_total_hash();
+                    node.state = State::NotReady;
+                    node.dependencies = self.graph.dependencies_in(id);
+                    node.received_at = Some(Instant::now());
+                    self.candidates.push(node);
+                    let _ = self.authenticate_node(id);
+
+                    // The new version replaced the buffered one, or was already delivered.
+                    let is_new = |e: &BufferEntry<H>| {
+                        e.id == id && e.state == State::Authenticated && e.payload.is_some() && e.compute_total_hash() == hash
+                    };
+                    if self.buffer[index!(self, id)].as_ref().is_some_and(is_new) || self.delivered.iter().any(is_new) {
+                        return Ok(());
+                    }
+                } else {
+                    self.flood.nb_rejected += 1;
+                }
+            }
+
+            return Err(Error::ConflictingDuplicate);
         }
 
-        // Just be sure that the node is not ready yet.
+        self.stats.on_received(id);
+
+        // Just be sure that the node is not ready yet, and that it follows our scheme.
         node.state = State::NotReady;
+        node.dependencies = self.graph.dependencies_in(id);
+        node.received_at = Some(Instant::now());
         // Insert the node.
-        self.buffer[index!(idx)] = Some(node);
+        self.buffer[idx] = Some(node);
+        self.observe(|o| o.on_insert(id));
 
         // Try to authenticate the node either using the (optional) digital signature,
         // or if a parent node has hashes.
@@ -50,83 +326,483 @@ impl RecvBuf for Buffer {
         Ok(())
     }
 
-    fn authenticate_node(&mut self, id: u64) -> Result<()> {
-        let entry_opt = self.buffer[index!(id)].as_mut();
-        if let Some(entry) = entry_opt {
-            if entry.id != id {
-                return Ok(());
+    fn decode(&self, packet: Bytes, format: WireFormat) -> Result<BufferEntry<H>> {
+        let expected_id = self.expected_id();
+        let res = BufferEntry::decode_near(packet.clone(), &self.graph, format, expected_id);
+        if !matches!(res, Err(Error::InvalidScheme)) {
+            return res;
+        }
+
+        // The switches announced by the buffered nodes, to the scheme of the packet.
+        let trailer = Trailer::decode(&packet)?;
+        let mut switches: Vec<Switch> = self
+            .buffer
+            .iter()
+            .flatten()
+            .chain(self.candidates.iter())
+            .filter_map(|entry| entry.switch)
+            .filter(|switch| (switch.a, switch.p) == (trailer.a, trailer.p))
+            .collect();
+        switches.sort_by_key(|switch| self.graph.offset(switch.start));
+        switches.dedup();
+
+        for switch in switches {
+            let mut graph = self.graph.clone();
+            if graph.switch(switch).is_err() {
+                continue;
             }
-    
-            if entry.state == State::Authenticated {
-                // Assumes that children nodes have been processed already in this context.
-                return Ok(());
+            if let Ok(node) = BufferEntry::decode_near(packet.clone(), &graph, format, expected_id) {
+                return Ok(node);
             }
-    
-            // Authenticate the node if it contains a digital signature.
-            // Otherwise, try to call an authenticated parent to authenticate this node.
-            if let Some(_sign) = entry.signature.as_ref() {
-                // TODO.
-                // For now assumes that it is always true.
-                entry.state = State::Authenticated;
-            } else {
-                // Compute the hash of this node to verify the match with the parent.
-                let node_hash = entry.compute_total_hash();
-    
-                // Iterate over its parents, hopefully find an authenticated node to authenticate this one.
-                for parent_id in entry.dependencies_out() {
-                    let parent_opt = self.buffer[index!(parent_id)].as_ref();
-
-                    if let Some(parent) = parent_opt {
-                        // Cannot use this parent because it is not already here or it is not authenticated.
-                        if parent.id != parent_id || parent.state != State::Authenticated {
-                            continue;
-                        }
-        
-                        // The parent is authenticated (yeay!) so we can match the hash to authenticate this one.
-                        match parent.compare_hash(&node_hash) {
-                            Ok(()) => {
-                                self.buffer[index!(id)].as_mut().unwrap().state = State::Authenticated;
-                                break;
-                            },
-                            Err(Error::NotAuthenticated) => continue,
-                            Err(e) => return Err(e),
+        }
+        res
+    }
+
+    fn authenticate_node(&mut self, id: u64) -> Result<()> {
+        // Only the children of a node that has just been authenticated may be authenticated in turn.
+        // A worklist avoids a recursion as deep as the buffer on long chains.
+        let mut worklist = vec![id];
+        let mut res = Ok(());
+        while let Some(node_id) = worklist.pop() {
+            match self.authenticate_one(node_id) {
+                Ok(true) => {
+                    let idx = index!(self, node_id);
+                    if self.delivery_mode == DeliveryMode::Immediate {
+                        let entry = self.buffer[idx].take().unwrap();
+                        self.buffer[idx] = Some(entry.without_payload());
+                        self.delivered.push_back(entry);
+                    }
+
+                    // The announced switch is authentic, and the buffer may grow with it.
+                    // It applies first since it may change the dependencies of the node.
+                    if let Some(switch) = self.buffer[idx].as_ref().unwrap().switch {
+                        if let Err(e) = self.apply_switch(switch) {
+                            self.observe(|o| o.on_failure(node_id, e));
+                            if node_id == id {
+                                res = Err(e);
+                            }
                         }
                     }
-                    
+
+                    let entry = self.buffer[index!(self, node_id)].as_ref().unwrap();
+                    worklist.extend(entry.dependencies.iter().rev());
+                },
+                Ok(false) => (),
+                Err(e) if node_id == id => res = Err(e),
+                // Keep authenticating the other nodes, nothing will trigger them again.
+                Err(_) => (),
+            }
+        }
+
+        res
+    }
+
+    fn report(&mut self) -> Report {
+        // A node cannot be authenticated anymore if its signature is invalid, or if it has no
+        // signature and all its parents are lost, badly authenticated or unverifiable themselves.
+        let mut unverifiable: HashSet<u64> = self
+            .buffer
+            .iter()
+            .flatten()
+            .filter(|entry| self.in_window(entry.id) && entry.state == State::BadAuthentication)
+            .map(|entry| entry.id)
+            .collect();
+
+        // The hashes mostly go to later nodes, so a single pass from the end of the window
+        // usually reaches the fixpoint.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in (0..self.capacity() as u64).rev().map(|i| self.lowest_id.wrapping_add(i)) {
+                let Some(entry) = self.buffer[index!(self, id)].as_ref().filter(|entry| entry.id == id) else {
+                    continue;
+                };
+                if entry.state != State::NotReady || entry.signature.is_some() || unverifiable.contains(&id) {
+                    continue;
+                }
+
+                let is_unusable = |parent_id: u64| match self.buffer[index!(self, parent_id)].as_ref() {
+                    Some(parent) if parent.id == parent_id => unverifiable.contains(&parent_id),
+                    _ => self.stats.is_lost(parent_id),
+                };
+                if self.graph.dependencies_out(id).into_iter().all(is_unusable) {
+                    unverifiable.insert(id);
+                    changed = true;
                 }
             }
-    
-            let entry = self.buffer[index!(id)].as_mut().unwrap();
-            match entry.state {
-                // Recursively call children if the current node has been identified.
-                State::Authenticated => {
-                    for children in entry.dependencies.clone() {
-                        self.authenticate_node(children)?;
-                    }
-                },
-    
-                State::BadAuthentication => return Err(Error::BadAuthentication),
-    
-                _ => (),
+        }
+        let nb_unverifiable = unverifiable.len();
+
+        self.stats.take_report(nb_unverifiable as u64)
+    }
+
+    fn set_release_policy(&mut self, policy: ReleasePolicy) {
+        self.release_policy = policy;
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Duration>) {
+        self.deadline = deadline;
+    }
+
+    fn set_join_policy(&mut self, policy: JoinPolicy) {
+        self.join_policy = policy;
+    }
+
+    fn set_max_capacity(&mut self, max_capacity: usize) {
+        self.max_capacity = max_capacity.max(self.capacity());
+    }
+
+    fn set_max_candidates(&mut self, max_candidates: usize) {
+        self.max_candidates = max_candidates;
+    }
+
+    fn flood_stats(&self) -> FloodStats {
+        self.flood
+    }
+
+    fn set_delivery_mode(&mut self, mode: DeliveryMode) {
+        self.delivery_mode = mode;
+    }
+
+    fn pop_released(&mut self) -> Vec<Released<H>> {
+        let mut out: Vec<Released<H>> = self.released.drain(..).collect();
+        let now = Instant::now();
+        loop {
+            out.extend(self.pop_ready_in_sequence().into_iter().map(Released::Authenticated));
+
+            if let Some(deadline) = self.deadline {
+                let received_at = self.first_received_at(self.lowest_id);
+                if received_at.is_some_and(|received_at| now.duration_since(received_at) >= deadline) {
+                    out.push(self.release_lowest(true));
+                    continue;
+                }
+            }
+
+            let ReleasePolicy::LossHorizon(horizon) = self.release_policy else {
+                break;
+            };
+
+            // The first node may still be received or authenticated until the loss horizon,
+            // counted from the highest authenticated node so that forged nodes do not move it.
+            let id = self.lowest_id;
+            let offset = |id: u64| self.graph.offset(id);
+            let is_beyond = |highest: &u64| offset(*highest) >= offset(id) + horizon;
+            let Some(highest) = self.highest_authenticated.filter(is_beyond) else {
+                break;
+            };
+
+            let is_received = self.buffer[index!(self, id)].as_ref().is_some_and(|e| e.id == id);
+            let is_lost = |id: u64| offset(id) < offset(self.lowest_id) || offset(highest) >= offset(id) + horizon;
+            if is_received && self.may_authenticate(id, is_lost) {
+                break;
+            }
+
+            out.push(self.release_lowest(false));
+        }
+
+        // The authenticated nodes are already delivered.
+        if self.delivery_mode == DeliveryMode::Immediate {
+            out.retain(|node| !matches!(node, Released::Authenticated(_)));
+            out.splice(0..0, self.delivered.drain(..).map(Released::Authenticated));
+        }
+
+        out
+    }
+}
+
+impl<H: PacketHasher> Buffer<H> {
+    /// Inserts the parked nodes the window reached, and drops those it already passed.
+    fn insert_parked(&mut self) {
+        if self.parked.iter().all(|node| !self.in_window(node.id)) {
+            return;
+        }
+        for node in std::mem::take(&mut self.parked) {
+            if self.in_window(node.id) {
+                let _ = self.insert(node);
+            } else if self.graph.offset(node.id) >= self.graph.offset(self.lowest_id) {
+                self.parked.push_back(node);
             }
         }
-        
+    }
+
+    /// Starts the window of the buffer with the first node, following the join policy.
+    fn join(&mut self, node: &BufferEntry<H>) -> Result<()> {
+        let start = match self.join_policy {
+            JoinPolicy::Start => self.lowest_id,
+            JoinPolicy::FirstPacket => self.graph.row_head(node.id),
+            JoinPolicy::FirstSigned => {
+                if node.signature.is_none() {
+                    return Err(Error::NotAuthenticated);
+                }
+                if !self.verifies_signature(node) {
+                    self.flood.nb_forged += 1;
+                    return Err(Error::BadAuthentication);
+                }
+                node.id
+            },
+        };
+
+        // Nodes before the initial ID belong to another stream.
+        if !self.graph.precedes_start(start) && self.graph.offset(start) > self.graph.offset(self.lowest_id) {
+            self.lowest_id = start;
+            self.latest_id = start.wrapping_sub(1);
+        }
+        self.joined = true;
+        self.is_provisional = self.join_policy == JoinPolicy::FirstPacket;
         Ok(())
     }
+
+    /// Discards the window started at a node that is not authenticated, and starts it again with the node.
+    /// The buffered nodes are counted as forged.
+    fn rejoin(&mut self, node: &BufferEntry<H>) -> Result<()> {
+        let mut forged: Vec<u64> = self.candidates.drain(..).map(|c| c.id).collect();
+        forged.extend(self.buffer.iter_mut().filter_map(Option::take).map(|e| e.id));
+        self.flood.nb_forged += forged.len() as u64;
+        for id in forged {
+            self.observe(|o| o.on_evict(id, Eviction::Forged));
+        }
+
+        self.released.clear();
+        self.stats = LossStats::default();
+        self.lowest_id = self.graph.start();
+        self.latest_id = self.lowest_id.wrapping_sub(1);
+        self.joined = false;
+        self.join(node)
+    }
+
+    /// Whether the node carries a digital signature that verifies.
+    fn verifies_signature(&self, node: &BufferEntry<H>) -> bool {
+        let Some(sign) = node.signature.as_ref() else {
+            return false;
+        };
+        // The signature is computed over the total hash of the node.
+        let node_hash = node.compute_total_hash();
+        self.verifier
+            .as_ref()
+            .is_some_and(|verifier| verifier.verify(node_hash.as_ref(), sign).is_ok())
+    }
+
+    /// Whether the node may still be authenticated, i.e., whether a path of received parents that
+    /// are not badly authenticated leads to an authenticated node, or to a node that is not lost.
+    fn may_authenticate(&self, id: u64, is_lost: impl Fn(u64) -> bool) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+
+            match self.buffer[index!(self, id)].as_ref().filter(|e| e.id == id) {
+                Some(entry) => match entry.state {
+                    State::Authenticated => return true,
+                    State::NotReady if entry.signature.is_none() => stack.extend(self.graph.dependencies_out(id)),
+                    _ => (),
+                },
+                None if !is_lost(id) => return true,
+                None => (),
+            }
+        }
+
+        false
+    }
+
+    /// Arrival time of the first node received at or after the ID, in the window of the buffer.
+    fn first_received_at(&self, id: u64) -> Option<Instant> {
+        (id.wrapping_sub(self.lowest_id)..self.capacity() as u64).find_map(|i| {
+            let id = self.lowest_id.wrapping_add(i);
+            let entry = self.buffer[index!(self, id)].as_ref().filter(|e| e.id == id)?;
+            entry.received_at
+        })
+    }
+
+    /// Releases the first node of the buffer, whether it is authenticated or not.
+    /// A node that is not authenticated is released as expired or unverified.
+    fn release_lowest(&mut self, is_expired: bool) -> Released<H> {
+        let id = self.lowest_id;
+        let idx = index!(self, id);
+        self.lowest_id = id.wrapping_add(1);
+        self.candidates.retain(|c| c.id != id);
+
+        match self.buffer[idx].take_if(|e| e.id == id) {
+            Some(entry) if entry.state == State::Authenticated => {
+                // Keep the hashes for the children arriving later, as `pop_ready_in_sequence` does.
+                self.buffer[idx] = Some(entry.without_payload());
+                let waited = entry.waited();
+                self.observe(|o| o.on_pop(id, waited));
+                Released::Authenticated(entry)
+            },
+            Some(entry) => {
+                self.stats.on_unverified();
+                if is_expired {
+                    self.observe(|o| o.on_evict(id, Eviction::Expired));
+                    Released::Expired(entry)
+                } else {
+                    self.observe(|o| o.on_evict(id, Eviction::Unverified));
+                    Released::Unverified(entry)
+                }
+            },
+            None => {
+                self.observe(|o| o.on_evict(id, Eviction::Lost));
+                Released::Dropped(id)
+            },
+        }
+    }
+
+    /// Tries to authenticate the node alone, with any of its versions.
+    /// The first version authenticated replaces the others.
+    /// Returns whether the node has just been authenticated.
+    fn authenticate_one(&mut self, id: u64) -> Result<bool> {
+        let idx = index!(self, id);
+        if !self.buffer[idx].as_ref().is_some_and(|e| e.id == id && e.state != State::Authenticated) {
+            return Ok(false);
+        }
+
+        let mut res = self.authenticate_version(id);
+        let mut tried = Vec::new();
+        while !matches!(res, Ok(true)) {
+            let Some(pos) = self.candidates.iter().position(|c| c.id == id) else {
+                break;
+            };
+            let candidate = self.candidates.swap_remove(pos);
+            tried.extend(self.buffer[idx].replace(candidate));
+
+            res = match (res, self.authenticate_version(id)) {
+                (_, Ok(true)) => Ok(true),
+                (Err(e), _) | (Ok(false), Err(e)) => Err(e),
+                _ => Ok(false),
+            };
+        }
+
+        let nb_candidates = self.candidates.len();
+        let nb_forged = if matches!(res, Ok(true)) {
+            // The other versions are forged.
+            self.candidates.retain(|c| c.id != id);
+            tried.len() + nb_candidates - self.candidates.len()
+        } else {
+            self.candidates.append(&mut tried);
+
+            // Keep a version that may still be authenticated in the buffer.
+            let is_bad = self.buffer[idx].as_ref().is_some_and(|e| e.state == State::BadAuthentication);
+            let pos = self.candidates.iter().position(|c| c.id == id && c.state == State::NotReady);
+            if let Some(pos) = pos.filter(|_| is_bad) {
+                let candidate = self.candidates.swap_remove(pos);
+                self.candidates.extend(self.buffer[idx].replace(candidate));
+            }
+
+            // The other versions that cannot be authenticated anymore are discarded.
+            let nb_candidates = self.candidates.len();
+            self.candidates.retain(|c| c.id != id || c.state != State::BadAuthentication);
+            nb_candidates - self.candidates.len()
+        };
+
+        self.flood.nb_forged += nb_forged as u64;
+        for _ in 0..nb_forged {
+            self.observe(|o| o.on_evict(id, Eviction::Forged));
+        }
+
+        res
+    }
+
+    /// Tries to authenticate the version of the node in the buffer.
+    /// Returns whether the node has just been authenticated.
+    fn authenticate_version(&mut self, id: u64) -> Result<bool> {
+        let idx = index!(self, id);
+        let Some(entry) = self.buffer[idx].as_mut() else {
+            return Ok(false);
+        };
+        if entry.id != id || entry.state == State::Authenticated {
+            return Ok(false);
+        }
+        // The version does not change, neither does the outcome.
+        if entry.state == State::BadAuthentication {
+            return Err(Error::BadAuthentication);
+        }
+
+        // Authenticate the node if it contains a digital signature.
+        // Otherwise, try to call an authenticated parent to authenticate this node.
+        let method;
+        if let Some(sign) = entry.signature.as_ref() {
+            // The signature is computed over the total hash of the node.
+            let node_hash = entry.compute_total_hash();
+            let is_valid = self
+                .verifier
+                .as_ref()
+                .is_some_and(|verifier| verifier.verify(node_hash.as_ref(), sign).is_ok());
+
+            if !is_valid {
+                entry.state = State::BadAuthentication;
+                self.flood.nb_bad_authentications += 1;
+                self.observe(|o| o.on_failure(id, Error::BadAuthentication));
+                return Err(Error::BadAuthentication);
+            }
+            method = AuthMethod::Signature;
+        } else {
+            // Compute the hash of this node to verify the match with the parent.
+            let node_hash = entry.compute_total_hash();
+
+            // Iterate over its parents, hopefully find an authenticated node to authenticate this one.
+            let mut authenticated_by = None;
+            for parent_id in self.graph.dependencies_out(id) {
+                let Some(parent) = self.buffer[index!(self, parent_id)].as_ref() else {
+                    continue;
+                };
+
+                // Cannot use this parent because it is not already here or it is not authenticated.
+                if parent.id != parent_id || parent.state != State::Authenticated {
+                    continue;
+                }
+
+                // The parent is authenticated (yeay!) so we can match the hash to authenticate this one.
+                match parent.compare_hash(&node_hash) {
+                    Ok(()) => {
+                        authenticated_by = Some(parent_id);
+                        break;
+                    },
+                    Err(Error::NotAuthenticated) => continue,
+                    Err(e) => {
+                        // An authenticated parent holds the hashes of all its children, the node is forged.
+                        self.buffer[idx].as_mut().unwrap().state = State::BadAuthentication;
+                        self.flood.nb_bad_authentications += 1;
+                        self.observe(|o| o.on_failure(id, e));
+                        return Err(e);
+                    },
+                }
+            }
+
+            let Some(parent_id) = authenticated_by else {
+                return Ok(false);
+            };
+            method = AuthMethod::Parent(parent_id);
+        }
+
+        let entry = self.buffer[idx].as_mut().unwrap();
+        entry.state = State::Authenticated;
+        self.is_provisional = false;
+        if self.highest_authenticated.is_none_or(|highest| self.graph.offset(id) > self.graph.offset(highest)) {
+            self.highest_authenticated = Some(id);
+        }
+        let waited = entry.waited();
+        self.stats.on_authenticated();
+        self.observe(|o| o.on_authenticate(id, method, waited));
+        Ok(true)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use ed25519_dalek::SigningKey;
+
     use super::*;
+    use crate::sign::{SignatureAlgorithm, Signer};
+    use crate::Signature;
 
-    #[test]
-    fn test_recv_buffer() {
-        // First create a sequence of authenticated packets.
-        let mut sb = Buffer::new(true);
+    /// Creates a sequence of nodes ready to be sent, without signature.
+    fn create_nodes(nb_nodes: usize) -> Vec<BufferEntry> {
+        let mut sb: Buffer = Buffer::new(Scheme::default(), true);
 
         let mut nodes = Vec::new();
         let mut id = 0;
-        while nodes.len() < 60 {
+        while nodes.len() < nb_nodes {
             // Push as much packets as possible.
             id = sb.push_pkts(id);
 
@@ -137,26 +813,40 @@ mod tests {
             nodes.extend(sb.pop_ready_in_sequence());
         }
 
-        // TODO: add some signatures to the nodes.
-        // We will add to each five node.
+        nodes
+    }
+
+    fn sign(node: &mut BufferEntry, key: &SigningKey) {
+        let hash = node.compute_total_hash();
+        node.signature = Some(key.sign(&hash));
+    }
+
+    #[test]
+    fn test_recv_buffer() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+
+        // First create a sequence of authenticated packets.
+        let mut nodes = create_nodes(60);
+
+        // We will add a signature to each five node.
         for i in 1..12 {
-            nodes[5 * i].signature = Some([1; 64]);
+            sign(&mut nodes[5 * i], &key);
+        }
+        if let Some(n) = nodes.last_mut() {
+            sign(n, &key);
         }
-        nodes.last_mut().map(|n| n.signature = Some([1; 64]));
 
         // Now that we got all authenticated nodes, we will add it to the receive buffer.
-        let mut rb = Buffer::new(false);
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
 
         let mut authenticated_nodes = Vec::new();
 
         for node in nodes.drain(..) {
                 // Push as many nodes as possible.
-                if let Err(_) = rb.insert(node) {
-                    assert!(false);
-                }
+                assert!(rb.insert(node).is_ok());
 
                 // Authenticate as many nodes as possible.
-                for i in 0..BUFF_SIZE {
+                for i in 0..rb.capacity() {
                     let _ = rb.authenticate_node(i as u64 + rb.lowest_id);
                 }
 
@@ -164,9 +854,516 @@ mod tests {
                 authenticated_nodes.extend(rb.pop_ready_in_sequence());
             }
 
-        assert_eq!(authenticated_nodes.len(), 56);
+        assert_eq!(authenticated_nodes.len(), 60);
         for node in authenticated_nodes.iter() {
             assert_eq!(node.state, State::Authenticated);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_recv_buffer_keeps_popped_hashes() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut nodes = create_nodes(10);
+        sign(&mut nodes[0], &key);
+        let copy = create_nodes(10).remove(0);
+
+        // The signed head of the row is popped before the next node of its row arrives.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_release_policy(ReleasePolicy::Blocking);
+        let mut nodes = nodes.into_iter();
+        assert_eq!(rb.insert(nodes.next().unwrap()), Ok(()));
+        let popped = rb.pop_ready_in_sequence();
+        assert_eq!(popped.len(), 1);
+        assert!(popped[0].payload().is_some());
+
+        // Its hashes are kept without the payload, and authenticate the next node.
+        let ghost = rb.buffer[0].as_ref().unwrap();
+        assert_eq!((ghost.id, ghost.state, ghost.payload()), (0, State::Authenticated, None));
+        assert_eq!(rb.insert(nodes.next().unwrap()), Ok(()));
+        assert_eq!(rb.pop_ready_in_sequence().iter().map(|n| n.id).collect::<Vec<_>>(), [1]);
+
+        // A late copy of the popped node is not delivered twice.
+        assert_eq!(rb.lowest_id, 2);
+        assert_eq!(rb.insert(copy), Err(Error::OutOfBoundId));
+    }
+
+    #[test]
+    fn test_recv_buffer_report() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut nodes = create_nodes(40);
+        nodes.truncate(40);
+        for i in 1..8 {
+            sign(&mut nodes[5 * i], &key);
+        }
+
+        // Both parents of node 22 are lost, and node 23 only has node 22 left.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        for node in nodes.drain(..).filter(|n| ![12, 13, 21, 24].contains(&n.id)) {
+            assert_eq!(rb.insert(node), Ok(()));
+            rb.pop_ready_in_sequence();
+        }
+        assert_eq!(rb.lowest_id, 12);
+
+        let report = rb.report();
+        assert_eq!(
+            report,
+            Report {
+                highest_id: 39,
+                nb_received: 36,
+                nb_lost: 4,
+                nb_authenticated: 34,
+                nb_unverifiable: 2,
+                bursts: [2, 1, 0, 0, 0, 0],
+                longest_burst: 2,
+            }
+        );
+
+        // The counters are reset, and the unverifiable nodes are still there.
+        let report = rb.report();
+        assert_eq!((report.nb_received, report.nb_unverifiable), (0, 2));
+    }
+
+    #[test]
+    fn test_recv_buffer_release() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut nodes = create_nodes(60);
+        nodes.truncate(60);
+        for i in 1..12 {
+            sign(&mut nodes[5 * i], &key);
+        }
+
+        // Same losses as above, the stream goes on after the unverifiable nodes.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        let mut released = Vec::new();
+        for node in nodes.drain(..).filter(|n| ![12, 13, 21, 24].contains(&n.id)) {
+            assert_eq!(rb.insert(node), Ok(()));
+            released.extend(rb.pop_released());
+        }
+
+        assert_eq!(released.len(), 60);
+        for (i, node) in released.iter().enumerate() {
+            assert_eq!(node.id(), i as u64);
+            match node {
+                Released::Dropped(id) => assert!([12, 13, 21, 24].contains(id)),
+                Released::Unverified(entry) => assert!([22, 23].contains(&entry.id)),
+                Released::Authenticated(entry) => assert_eq!(entry.state, State::Authenticated),
+                Released::Expired(_) => panic!("nodes do not expire by default"),
+            }
+        }
+        let nb_unverified = released.iter().filter(|node| matches!(node, Released::Unverified(_))).count();
+        assert_eq!(nb_unverified, 2);
+
+        let report = rb.report();
+        assert_eq!((report.nb_lost, report.nb_unverifiable), (4, 2));
+    }
+
+    #[test]
+    fn test_recv_buffer_release_window() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signed_far = || {
+            let mut far = create_nodes(50).remove(40);
+            sign(&mut far, &key);
+            far
+        };
+
+        // The first nodes wait for a signature that is lost.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_release_policy(ReleasePolicy::Blocking);
+        for node in create_nodes(3).into_iter().take(3) {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        assert_eq!(rb.insert(signed_far()), Err(Error::OutOfBoundId));
+
+        // A node after the window whose signature does not verify releases nothing, and waits.
+        rb.set_release_policy(ReleasePolicy::LossHorizon(DEFAULT_LOSS_HORIZON));
+        assert_eq!(rb.insert(create_nodes(50).remove(39)), Ok(()));
+        let mut forged = create_nodes(50).remove(40);
+        sign(&mut forged, &SigningKey::from_bytes(&[8; 32]));
+        assert_eq!(rb.insert(forged), Ok(()));
+        assert_eq!(rb.lowest_id, 0);
+        assert_eq!(rb.parked.len(), 2);
+        assert!(rb.pop_released().is_empty());
+
+        // The signed node after the window makes room for itself and the waiting nodes.
+        assert_eq!(rb.insert(signed_far()), Ok(()));
+        assert_eq!(rb.lowest_id, 40 - rb.capacity() as u64 + 1);
+        assert!(rb.parked.is_empty());
+        assert_eq!(rb.buffer[index!(rb, 39)].as_ref().unwrap().state, State::Authenticated);
+
+        // The missing nodes before the loss horizon are released as well.
+        let released = rb.pop_released();
+        assert_eq!(released.len(), 40 - DEFAULT_LOSS_HORIZON as usize + 1);
+        assert!(released[..3].iter().all(|node| matches!(node, Released::Unverified(_))));
+        assert!(released[3..].iter().all(|node| matches!(node, Released::Dropped(_))));
+
+        // Too far ahead.
+        let too_far = create_nodes(120).remove(110);
+        assert_eq!(rb.insert(too_far), Err(Error::OutOfBoundId));
+    }
+
+    #[test]
+    fn test_recv_buffer_expiry() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+
+        // The nodes wait for a signature that never comes.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_release_policy(ReleasePolicy::Blocking);
+        rb.set_deadline(Some(Duration::from_secs(3600)));
+        for node in create_nodes(10).into_iter().take(10).filter(|n| n.id != 3) {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        assert!(rb.pop_released().is_empty());
+
+        // The deadline applies regardless of the release policy.
+        rb.set_deadline(Some(Duration::ZERO));
+        let released = rb.pop_released();
+        assert_eq!(released.len(), 10);
+        for (i, node) in released.iter().enumerate() {
+            assert_eq!(node.id(), i as u64);
+            if i == 3 {
+                assert!(matches!(node, Released::Dropped(3)));
+            } else {
+                assert!(matches!(node, Released::Expired(entry) if entry.received_at().is_some()));
+            }
+        }
+        assert_eq!(rb.report().nb_unverifiable, 9);
+    }
+
+    #[test]
+    fn test_recv_buffer_immediate() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut nodes = create_nodes(20);
+        nodes.truncate(20);
+        for i in 1..4 {
+            sign(&mut nodes[5 * i], &key);
+        }
+
+        // The signed row is delivered before the previous ones.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_delivery_mode(DeliveryMode::Immediate);
+        let mut later: Vec<_> = nodes.drain(..10).collect();
+        for node in nodes.drain(..5) {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        let delivered = rb.pop_released();
+        assert_eq!(delivered.iter().map(|n| n.id()).collect::<Vec<_>>(), (10..15).collect::<Vec<_>>());
+        assert!(delivered.iter().all(|n| matches!(n, Released::Authenticated(e) if e.payload().is_some())));
+        assert_eq!(rb.lowest_id, 0);
+
+        // The other nodes are delivered once, and the buffer moves on.
+        for node in later.drain(..) {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        let mut ids: Vec<u64> = rb.pop_released().iter().map(|n| n.id()).collect();
+        ids.sort();
+        assert_eq!(ids, (0..10).collect::<Vec<_>>());
+        assert_eq!(rb.lowest_id, 15);
+        assert!(rb.pop_released().is_empty());
+    }
+
+    #[test]
+    fn test_recv_buffer_grow() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut nodes = create_nodes(100);
+
+        // The signed node after the window fits once the buffer grows.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_release_policy(ReleasePolicy::Blocking);
+        rb.set_max_capacity(80);
+        let mut far = nodes.remove(40);
+        sign(&mut far, &key);
+        for node in nodes.drain(..3) {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        assert_eq!(rb.insert(far), Ok(()));
+        assert_eq!(rb.capacity(), 64);
+
+        // Up to the maximum capacity.
+        let mut far = nodes.remove(75);
+        sign(&mut far, &key);
+        assert_eq!(rb.insert(far), Ok(()));
+        assert_eq!(rb.capacity(), 80);
+        let mut far = nodes.remove(80);
+        sign(&mut far, &key);
+        assert_eq!(rb.insert(far), Err(Error::OutOfBoundId));
+
+        // The nodes are still authenticated once the signatures arrive.
+        for mut node in nodes.drain(..37) {
+            if node.id == 15 {
+                sign(&mut node, &key);
+            }
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        assert_eq!(rb.pop_released().len(), 41);
+    }
+
+    #[test]
+    fn test_recv_buffer_flood_ids() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut nodes = create_nodes(100);
+        nodes.truncate(100);
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_max_capacity(80);
+        let capacity = rb.capacity();
+
+        // Forged nodes after the window neither grow nor move it.
+        for node in nodes.iter().take(80).skip(capacity) {
+            let mut forged = create_nodes(100).remove(node.id as usize);
+            forged.payload = Some(vec![1; 20]);
+            assert_eq!(rb.insert(forged), Ok(()));
+        }
+        for node in nodes.drain(..capacity).filter(|node| node.id != 15) {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        assert_eq!(rb.capacity(), capacity);
+        assert_eq!(rb.lowest_id, 0);
+        assert_eq!(rb.parked.len(), capacity);
+        assert!(rb.pop_released().is_empty());
+        assert_eq!(rb.flood_stats().nb_out_of_window, 80 - capacity as u64);
+
+        // The legitimate nodes are authenticated once the signature arrives.
+        let mut signed = create_nodes(100).remove(15);
+        sign(&mut signed, &key);
+        assert_eq!(rb.insert(signed), Ok(()));
+        let released = rb.pop_released();
+        assert_eq!(released.len(), 20);
+        assert!(released.iter().all(|node| matches!(node, Released::Authenticated(_))));
+    }
+
+    #[test]
+    fn test_recv_buffer_conflicting_duplicate() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signed_nodes = || {
+            let mut nodes = create_nodes(10);
+            nodes.truncate(10);
+            sign(&mut nodes[5], &key);
+            nodes
+        };
+        let mut forged = signed_nodes();
+        forged[7].payload = Some(vec![1; 20]);
+        forged[5].signature = Some(Signature {
+            algorithm: SignatureAlgorithm::Ed25519,
+            bytes: vec![1; 64],
+        });
+
+        // The forged versions arrive first.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_release_policy(ReleasePolicy::Blocking);
+        let forged_5 = forged.remove(5);
+        let forged_7 = forged.remove(6);
+        assert_eq!(rb.insert(forged_7), Ok(()));
+        assert_eq!(rb.insert(forged_5), Err(Error::BadAuthentication));
+
+        // The legitimate versions are kept as candidates, and authenticated.
+        // Node 6 is authenticated first, and does not authenticate the forged version of node 7,
+        // which is only reported in the flood stats.
+        for node in signed_nodes() {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        assert!(rb.candidates.is_empty());
+        assert_eq!(rb.flood_stats().nb_bad_authentications, 2);
+
+        // Authenticated nodes cannot be replaced, and copies are ignored.
+        let mut nodes = signed_nodes();
+        assert_eq!(rb.insert(nodes.remove(6)), Ok(()));
+        nodes[5].payload = Some(vec![2; 20]);
+        assert_eq!(rb.insert(nodes.remove(5)), Err(Error::ConflictingDuplicate));
+        assert!(rb.candidates.is_empty());
+
+        let released = rb.pop_released();
+        assert_eq!(released.len(), 10);
+        for node in released {
+            let Released::Authenticated(node) = node else {
+                panic!("node {} not authenticated", node.id());
+            };
+            assert_eq!(node.payload(), Some(&[42; 20][..]));
+        }
+    }
+
+    #[test]
+    fn test_recv_buffer_flood() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signed_nodes = || {
+            let mut nodes = create_nodes(10);
+            nodes.truncate(10);
+            sign(&mut nodes[5], &key);
+            nodes
+        };
+        let junk = |id: usize, i: u8| {
+            let mut node = signed_nodes().remove(id);
+            node.payload = Some(vec![i; 20]);
+            node
+        };
+
+        // Junk versions arriving before the parents are authenticated are limited.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        assert_eq!(rb.insert(junk(7, 0)), Ok(()));
+        for i in 1..5 {
+            assert_eq!(rb.insert(junk(7, i)), Err(Error::ConflictingDuplicate));
+        }
+        assert_eq!(rb.candidates.len(), DEFAULT_MAX_CANDIDATES);
+        let stats = FloodStats {
+            nb_conflicts: 4,
+            nb_forged: 0,
+            nb_rejected: 2,
+            nb_bad_authentications: 0,
+            nb_out_of_window: 0,
+        };
+        assert_eq!(rb.flood_stats(), stats);
+
+        // The junk versions are discarded once the parents are authenticated,
+        // and the legitimate version replaces them.
+        for node in signed_nodes() {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        assert!(rb.candidates.is_empty());
+        let stats = FloodStats {
+            nb_conflicts: 5,
+            nb_forged: 3,
+            nb_rejected: 2,
+            nb_bad_authentications: 3,
+            nb_out_of_window: 0,
+        };
+        assert_eq!(rb.flood_stats(), stats);
+
+        let released = rb.pop_released();
+        assert_eq!(released.len(), 10);
+        assert!(released.iter().all(|node| matches!(node, Released::Authenticated(_))));
+
+        // The legitimate version arriving after the junk ones evicts the oldest candidate.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        assert_eq!(rb.insert(junk(7, 0)), Ok(()));
+        for i in 1..5 {
+            assert_eq!(rb.insert(junk(7, i)), Err(Error::ConflictingDuplicate));
+        }
+        let legit = signed_nodes().remove(7);
+        let payload = legit.payload.clone();
+        assert_eq!(rb.insert(legit), Err(Error::ConflictingDuplicate));
+        assert_eq!(rb.candidates.len(), DEFAULT_MAX_CANDIDATES);
+        let slot = rb.buffer[index!(rb, 7)].as_ref().unwrap();
+        assert!(slot.payload == payload || rb.candidates.iter().any(|c| c.payload == payload));
+        assert_eq!(rb.flood_stats().nb_rejected, 3);
+
+        for node in signed_nodes().into_iter().filter(|node| node.id != 7) {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        assert!(rb.candidates.is_empty());
+        assert_eq!(rb.flood_stats().nb_forged, 2);
+
+        let released = rb.pop_released();
+        assert_eq!(released.len(), 10);
+        match &released[7] {
+            Released::Authenticated(entry) => assert_eq!(entry.payload, payload),
+            released => panic!("node 7 is not authenticated: {released:?}"),
+        }
+    }
+
+    #[test]
+    fn test_recv_buffer_bad_signature() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let attacker_key = SigningKey::from_bytes(&[8; 32]);
+        let mut nodes = create_nodes(10);
+
+        // Random bytes as a signature.
+        nodes[5].signature = Some(Signature {
+            algorithm: SignatureAlgorithm::Ed25519,
+            bytes: vec![1; 64],
+        });
+        // Valid signature, but not from the sender.
+        sign(&mut nodes[6], &attacker_key);
+        // Valid signature over another node.
+        let hash = nodes[0].compute_total_hash();
+        nodes[7].signature = Some(key.sign(&hash));
+        sign(&mut nodes[8], &key);
+
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        for node in nodes.drain(..9) {
+            let id = node.id;
+            match id {
+                5..=7 => {
+                    assert_eq!(rb.insert(node), Err(Error::BadAuthentication));
+                    assert_eq!(rb.buffer[id as usize].as_ref().unwrap().state, State::BadAuthentication);
+                }
+                8 => {
+                    assert_eq!(rb.insert(node), Ok(()));
+                    assert_eq!(rb.buffer[id as usize].as_ref().unwrap().state, State::Authenticated);
+                }
+                _ => assert_eq!(rb.insert(node), Ok(())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_recv_buffer_join() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signed_nodes = || {
+            let mut nodes = create_nodes(100);
+            nodes.truncate(100);
+            for i in 1..20 {
+                sign(&mut nodes[5 * i], &key);
+            }
+            sign(&mut nodes[99], &key);
+            nodes.into_iter().map(Some).collect::<Vec<_>>()
+        };
+
+        // The receiver expecting the start of the stream rejects the nodes far ahead.
+        let mut nodes = signed_nodes();
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        assert_eq!(rb.insert(nodes[80].take().unwrap()), Err(Error::OutOfBoundId));
+
+        // The window starts at the row of the first node, and accepts the previous nodes of the row.
+        let mut nodes = signed_nodes();
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_join_policy(JoinPolicy::FirstPacket);
+        assert_eq!(rb.insert(nodes[82].take().unwrap()), Ok(()));
+        assert_eq!(rb.lowest_id, 80);
+        assert_eq!(rb.insert(nodes[81].take().unwrap()), Ok(()));
+        assert_eq!(rb.insert(nodes[31].take().unwrap()), Err(Error::OutOfBoundId));
+        for node in nodes[83..].iter_mut().filter_map(Option::take) {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        // The head of the row was sent before joining.
+        let released = rb.pop_released();
+        assert!(matches!(released[0], Released::Dropped(80)));
+        assert_eq!(released.len(), 20);
+        assert!(released[1..].iter().all(|node| matches!(node, Released::Authenticated(_))));
+
+        // The window started at a forged node moves to the first signed node of the stream.
+        let mut nodes = signed_nodes();
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_join_policy(JoinPolicy::FirstPacket);
+        assert_eq!(rb.insert(BufferEntry::dummy(1_000_000)), Ok(()));
+        for node in nodes[..5].iter_mut().filter_map(Option::take) {
+            assert_eq!(rb.insert(node), Err(Error::OutOfBoundId));
+        }
+        let mut released = Vec::new();
+        for node in nodes[5..].iter_mut().filter_map(Option::take) {
+            assert_eq!(rb.insert(node), Ok(()));
+            released.extend(rb.pop_released());
+        }
+        assert_eq!(rb.flood_stats().nb_forged, 1);
+        assert_eq!(released.iter().map(|node| node.id()).collect::<Vec<_>>(), (5..100).collect::<Vec<_>>());
+        assert!(released.iter().all(|node| matches!(node, Released::Authenticated(_))));
+
+        // Once a node is authenticated, the window does not move anymore.
+        assert_eq!(rb.insert(BufferEntry::dummy(1_000_000)), Err(Error::OutOfBoundId));
+
+        // The window starts at the first signed node, the nodes before and the forged ones are rejected.
+        let mut nodes = signed_nodes();
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_join_policy(JoinPolicy::FirstSigned);
+        for node in nodes[81..85].iter_mut().filter_map(Option::take) {
+            assert_eq!(rb.insert(node), Err(Error::NotAuthenticated));
+        }
+        let mut forged = signed_nodes()[85].take().unwrap();
+        sign(&mut forged, &SigningKey::from_bytes(&[8; 32]));
+        assert_eq!(rb.insert(forged), Err(Error::BadAuthentication));
+        assert_eq!(rb.flood_stats().nb_forged, 1);
+        for node in nodes[85..].iter_mut().filter_map(Option::take) {
+            assert_eq!(rb.insert(node), Ok(()));
+        }
+        assert_eq!(rb.lowest_id, 85);
+        let released = rb.pop_released();
+        assert_eq!(released.iter().map(|node| node.id()).collect::<Vec<_>>(), (85..100).collect::<Vec<_>>());
+    }
+}
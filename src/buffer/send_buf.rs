@@ -1,47 +1,169 @@
+use std::time::Duration;
+use std::time::Instant;
+
 use super::Buffer;
 use super::BufferEntry;
 use super::State;
-use super::BUFF_SIZE;
+use crate::graph::Graph;
+use crate::graph::Switch;
+use crate::hash::PacketHasher;
+use crate::scheme::Scheme;
+use crate::sign::Signer;
 use crate::Error;
 use crate::Result;
 
+/// Number of blocks of the new scheme during which the signed nodes still announce a switch,
+/// for the receivers that lost all the announcements before it.
+pub const SWITCH_REANNOUNCE_BLOCKS: u64 = 2;
+
+/// Policy deciding which nodes are signed by the send buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignaturePolicy {
+    /// Signs the first node of a row once at least `n` nodes were processed since the last signature.
+    EveryN(usize),
+
+    /// Signs the first node of a row once the duration elapsed since the last signature.
+    Every(Duration),
+
+    /// Signs the first node of the last row of each block of a×p nodes.
+    /// This node is processed last in the block and its signature authenticates the whole block.
+    EndOfBlock,
+
+    /// Only signs nodes when explicitly requested with `SendBuffer::flush`.
+    OnFlush,
+}
+
+/// Decides which nodes must be signed, following the signature policy.
+pub(crate) struct SignatureSchedule {
+    /// The policy.
+    policy: SignaturePolicy,
+
+    /// Number of nodes processed since the last signature.
+    nb_unsigned: usize,
+
+    /// Time of the last signature.
+    last_signature: Option<Instant>,
+
+    /// Whether the next processed node must be signed, regardless of the policy.
+    flush: bool,
+}
+
+impl SignatureSchedule {
+    pub(crate) fn new(policy: SignaturePolicy) -> Self {
+        Self {
+            policy,
+            nb_unsigned: 0,
+            last_signature: None,
+            flush: false,
+        }
+    }
+
+    /// Whether the node whose hash is being forwarded must be signed.
+    /// Updates the state of the schedule accordingly.
+    fn must_sign(&mut self, id: u64, graph: &Graph) -> bool {
+        let now = Instant::now();
+        let last_signature = *self.last_signature.get_or_insert(now);
+        self.nb_unsigned += 1;
+
+        // The first node of a row is processed last in the row and carries the hashes of the previous rows.
+        let is_row_head = graph.is_row_head(id);
+
+        let must_sign = self.flush
+            || match self.policy {
+                SignaturePolicy::EveryN(n) => is_row_head && self.nb_unsigned >= n,
+                SignaturePolicy::Every(period) => is_row_head && now.duration_since(last_signature) >= period,
+                SignaturePolicy::EndOfBlock => graph.is_end_of_block(id),
+                SignaturePolicy::OnFlush => false,
+            };
+
+        if must_sign {
+            self.nb_unsigned = 0;
+            self.last_signature = Some(now);
+            self.flush = false;
+        }
+
+        must_sign
+    }
+}
+
 /// SendBuffer-specific methods.
-pub trait SendBuffer {
-    /// Creates a new receive buffer.
-    fn new() -> Self;
+pub trait SendBuffer<H: PacketHasher> {
+    /// Creates a new send buffer with the default a=3,p=5 scheme.
+    /// The signer is used to sign the total hash of the nodes.
+    fn new(signer: Box<dyn Signer>) -> Self;
+
+    /// Creates a new send buffer with the given scheme.
+    fn with_scheme(scheme: Scheme, signer: Box<dyn Signer>) -> Self;
 
     /// Inserts a new node in the graph.
     /// Calling this function assumes that the nodes are created in sequence.
     /// Returns an error otherwise.
-    fn insert_in_sequence(&mut self, node: BufferEntry) -> Result<()>;
+    fn insert_in_sequence(&mut self, node: BufferEntry<H>) -> Result<()>;
 
     /// Forwards its packet hash to its output dependencies.
     /// This function assumes that in-hashes are added sequentially,
     /// i.e., if they need two hashes and there are indeed two hashes, it assumes that
     /// the two hashes correspond to the intended nodes.
     /// Returns an error `MissingHash` if this node does not have all the required hashes to proceed.
+    /// The node is signed if required by the signature policy.
     fn forwards_hash(&mut self, id: u64) -> Result<()>;
+
+    /// Forwards the hashes of as many inserted nodes as possible, following the processing order.
+    /// Returns the number of processed nodes.
+    fn forward_ready(&mut self) -> usize;
+
+    /// Sets the policy deciding which nodes are signed in `forwards_hash`.
+    /// The default policy is `SignaturePolicy::EndOfBlock`.
+    fn set_signature_policy(&mut self, policy: SignaturePolicy);
+
+    /// Requests a signature on the next node whose hash is forwarded, regardless of the policy.
+    fn flush(&mut self);
+
+    /// Signs the total hash of the node.
+    /// Returns an error `MissingHash` if the hash of the node is not computed yet,
+    /// i.e., it is not ready to be sent.
+    fn sign_node(&mut self, id: u64) -> Result<()>;
+
+    /// Switches to the scheme at the first block boundary leaving at least a block of nodes
+    /// to announce it. All nodes inserted until then carry the announcement, and the signed nodes
+    /// of the first `SWITCH_REANNOUNCE_BLOCKS` blocks of the new scheme as well.
+    /// Returns the ID of the first node following the new scheme,
+    /// or an error `SwitchPending` if the previous switch is still announced.
+    fn switch_scheme(&mut self, scheme: Scheme) -> Result<u64>;
 }
 
-impl SendBuffer for Buffer {
-    fn new() -> Self {
-        Buffer::new(true)
+impl<H: PacketHasher> SendBuffer<H> for Buffer<H> {
+    fn new(signer: Box<dyn Signer>) -> Self {
+        Self::with_scheme(Scheme::default(), signer)
+    }
+
+    fn with_scheme(scheme: Scheme, signer: Box<dyn Signer>) -> Self {
+        let mut buffer = Buffer::new(scheme, true);
+        buffer.signer = Some(signer);
+        buffer
     }
 
-    fn insert_in_sequence(&mut self, node: BufferEntry) -> Result<()> {
-        if node.id < self.lowest_id || node.id >= self.lowest_id + BUFF_SIZE as u64 {
+    fn insert_in_sequence(&mut self, node: BufferEntry<H>) -> Result<()> {
+        if !self.in_window(node.id) {
             return Err(Error::OutOfBoundId);
         }
 
         // Check whether we are trying to add a "too old" ID in the buffer, or a too recent.
-        if node.id.saturating_sub(1) != self.latest_id {
+        if node.id.wrapping_sub(1) != self.latest_id {
             return Err(Error::IllegalInsert);
         }
 
+        // Announce the scheme switch until it applies.
+        let switch = self.pending_switch.filter(|switch| self.graph.offset(node.id) < self.graph.offset(switch.start));
+
         // Check if the node already exists.
         self.latest_id = node.id;
         let entry = self.get_or_create(node.id)?;
         entry.payload = node.payload;
+        entry.is_padding = node.is_padding;
+        entry.switch = switch;
+        entry.received_at = Some(Instant::now());
+        self.observe(|o| o.on_insert(node.id));
 
         Ok(())
     }
@@ -51,8 +173,11 @@ impl SendBuffer for Buffer {
     /// i.e., if they need two hashes and there are indeed two hashes, it assumes that
     /// the two hashes correspond to the intended nodes.
     /// Returns an error `MissingHash` if this node does not have all the required hashes to proceed.
+    /// The node is signed if required by the signature policy.
     fn forwards_hash(&mut self, id: u64) -> Result<()> {
-        let idx = index!(id);
+        let idx = index!(self, id);
+        let out_dep = self.graph.dependencies_out(id);
+        let window_end = self.graph.offset(self.lowest_id) + self.capacity() as u64;
         let entry_opt = self.buffer[idx].as_mut();
 
         if let Some(entry) = entry_opt {
@@ -70,46 +195,117 @@ impl SendBuffer for Buffer {
             }
     
             // Ensure that we can push this node only if we can already propagate its hashes.
-            if entry
-                .dependencies_out()
-                .iter()
-                .max()
-                .map(|&m| m >= self.lowest_id + BUFF_SIZE as u64)
-                .unwrap_or(false)
-            {
+            if out_dep.iter().any(|&m| self.graph.offset(m) >= window_end) {
                 return Err(Error::OutOfBoundId);
             }
     
-            // TODO: compute the hash of the node based on all the received hashes etc.
+            let signer = self.signer.as_ref().filter(|_| self.signature_schedule.must_sign(id, &self.graph));
+
+            // The signed nodes keep announcing the switch for a while after it applies.
+            if let Some(switch) = self.pending_switch {
+                let block = (switch.a * switch.p) as u64;
+                let (offset, start) = (self.graph.offset(id), self.graph.offset(switch.start));
+                if offset >= start + SWITCH_REANNOUNCE_BLOCKS * block {
+                    self.pending_switch = None;
+                } else if offset >= start && signer.is_some() {
+                    entry.switch = Some(switch);
+                }
+            }
+
+            // Compute the hash of the node based on its payload and all the received hashes.
             let hash = entry.compute_total_hash();
     
             // Node is now ready to be sent on the wire.
             entry.state = State::ReadySent;
+
+            if let Some(signer) = signer {
+                entry.signature = Some(signer.sign(hash.as_ref()));
+            }
+            let (is_signed, waited) = (entry.signature.is_some(), entry.waited());
     
             // Send the hashes to all exiting nodes in the graph.
-            let out_dep = entry.dependencies_out();
             for &next_node in out_dep.iter() {
                 let node = self.get_or_create(next_node)?;
                 node.hashes.push_back(hash);
             }
+            self.observe(|o| o.on_forward(id, is_signed, waited));
         }
 
         Ok(())
     }
+
+    fn forward_ready(&mut self) -> usize {
+        let mut nb_processed = 0;
+        let is_inserted = |b: &Self| b.graph.offset(b.next_node_id_hash) <= b.graph.offset(b.latest_id);
+        while is_inserted(self) && self.buffer[index!(self, self.next_node_id_hash)].is_some() {
+            let id = self.next_node_id_hash;
+            if self.forwards_hash(id).is_err() {
+                break;
+            }
+            self.next_node_id_hash();
+            nb_processed += 1;
+        }
+        nb_processed
+    }
+
+    fn set_signature_policy(&mut self, policy: SignaturePolicy) {
+        self.signature_schedule = SignatureSchedule::new(policy);
+    }
+
+    fn flush(&mut self) {
+        self.signature_schedule.flush = true;
+    }
+
+    fn sign_node(&mut self, id: u64) -> Result<()> {
+        let idx = index!(self, id);
+        let entry = self.buffer[idx]
+            .as_mut()
+            .filter(|e| e.id == id)
+            .ok_or(Error::OutOfBoundId)?;
+
+        if entry.state != State::ReadySent {
+            return Err(Error::MissingHash);
+        }
+
+        let signer = self.signer.as_ref().ok_or(Error::NotAuthenticated)?;
+        entry.signature = Some(signer.sign(entry.compute_total_hash().as_ref()));
+
+        Ok(())
+    }
+
+    fn switch_scheme(&mut self, scheme: Scheme) -> Result<u64> {
+        if self.pending_switch.is_some() {
+            return Err(Error::SwitchPending);
+        }
+
+        // Nodes already inserted may have sent their hash up to a block further with the current scheme.
+        let current = self.graph.last_scheme();
+        let min_start = self.latest_id.wrapping_add(1 + (current.a() * current.p()) as u64);
+        let switch = Switch {
+            start: self.graph.next_block_boundary(min_start),
+            a: scheme.a(),
+            p: scheme.p(),
+        };
+
+        self.apply_switch(switch)?;
+        self.pending_switch = Some(switch);
+
+        Ok(switch.start)
+    }
 }
 
 #[cfg(test)]
 mod testing {
     use super::*;
 
-    impl Buffer {
+    impl<H: PacketHasher> Buffer<H> {
         /// Push as many packets as possible in the buffer.
         /// Returns the next ID.
         pub fn push_pkts(&mut self, start_id: u64) -> u64 {
             let mut id = start_id;
             loop {
                 let entry = BufferEntry::dummy(id);
-                if let Err(_) = self.insert_in_sequence(entry) {
+                if self.insert_in_sequence(entry).is_err() {
                     break;
                 }
                 id += 1;
@@ -120,7 +316,7 @@ mod testing {
         /// Forward as many hashes as possible.
         /// Iterate over all elements of the buffer.
         pub fn forw_hash(&mut self) {
-            for i in 0..BUFF_SIZE {
+            for i in 0..self.capacity() {
                 let _ = self.forwards_hash(self.lowest_id + i as u64);
             }
         }
@@ -130,18 +326,26 @@ mod testing {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::buffer::bytes::WireFormat;
+    use crate::buffer::recv_buf::{RecvBuf, Released, ReleasePolicy, DEFAULT_LOSS_HORIZON};
+
+    fn signer() -> Box<dyn Signer> {
+        Box::new(ed25519_dalek::SigningKey::from_bytes(&[7; 32]))
+    }
 
     #[test]
     fn test_send_buffer() {
-        let mut sb: Buffer = SendBuffer::new();
+        let mut sb: Buffer = SendBuffer::new(signer());
+        let buff_size = sb.capacity();
+        assert_eq!(buff_size, 32);
 
-        for id in 0..BUFF_SIZE {
+        for id in 0..buff_size {
             let entry = BufferEntry::dummy(id as u64);
             assert_eq!(sb.insert_in_sequence(entry), Ok(()));
         }
 
         // Buffer is full.
-        let entry = BufferEntry::dummy(BUFF_SIZE as u64);
+        let entry = BufferEntry::dummy(buff_size as u64);
         assert_eq!(sb.insert_in_sequence(entry), Err(Error::OutOfBoundId));
 
         // Computing the hash of packets is only possible for the node index 3.
@@ -155,7 +359,7 @@ mod tests {
 
         for _ in 0..9 {
             let id = sb.next_node_id_hash();
-            if id as usize > BUFF_SIZE {
+            if id as usize > buff_size {
                 break;
             }
             assert_eq!(sb.forwards_hash(id), Ok(()));
@@ -164,9 +368,262 @@ mod tests {
             assert_eq!(entry.state, State::ReadySent);
         }
 
+        // Only nodes ready to be sent can be signed.
+        assert_eq!(sb.sign_node(0), Ok(()));
+        assert_eq!(sb.sign_node(10), Err(Error::MissingHash));
+        assert_eq!(sb.sign_node(buff_size as u64), Err(Error::OutOfBoundId));
+
         // Now all packets should be able to be sent on the wire.
         let out = sb.pop_ready_in_sequence();
+        assert!(out[0].signature.is_some());
+        assert!(out[1..].iter().all(|e| e.signature.is_none()));
         assert_eq!(out.len(), 5);
         assert_eq!(sb.lowest_id, 5);
     }
+
+    /// Generates the first `nb_nodes` nodes ready to be sent with the scheme and signature policy.
+    fn generate_with(scheme: Scheme, policy: SignaturePolicy, nb_nodes: usize) -> Vec<BufferEntry> {
+        let mut sb: Buffer = SendBuffer::with_scheme(scheme, signer());
+        sb.set_signature_policy(policy);
+
+        let mut nodes = Vec::new();
+        let mut id = 0;
+        while nodes.len() < nb_nodes {
+            id = sb.push_pkts(id);
+            while sb.forwards_hash(sb.next_node_id_hash) == Ok(()) {
+                sb.next_node_id_hash();
+            }
+            nodes.extend(sb.pop_ready_in_sequence());
+        }
+        nodes.truncate(nb_nodes);
+
+        nodes
+    }
+
+    fn generate(policy: SignaturePolicy, nb_nodes: usize) -> Vec<BufferEntry> {
+        generate_with(Scheme::default(), policy, nb_nodes)
+    }
+
+    fn signed_ids(nodes: &[BufferEntry]) -> Vec<u64> {
+        nodes.iter().filter(|n| n.signature.is_some()).map(|n| n.id).collect()
+    }
+
+    #[test]
+    fn test_signature_policy() {
+        let nodes = generate(SignaturePolicy::EndOfBlock, 60);
+        assert_eq!(signed_ids(&nodes), vec![10, 25, 40, 55]);
+
+        let nodes = generate(SignaturePolicy::EveryN(7), 60);
+        assert_eq!(signed_ids(&nodes), vec![5, 15, 25, 35, 45, 55]);
+
+        let nodes = generate(SignaturePolicy::EveryN(1), 60);
+        assert_eq!(signed_ids(&nodes), (0..60).step_by(5).collect::<Vec<_>>());
+
+        let nodes = generate(SignaturePolicy::Every(Duration::ZERO), 60);
+        assert_eq!(signed_ids(&nodes), (0..60).step_by(5).collect::<Vec<_>>());
+
+        let nodes = generate(SignaturePolicy::Every(Duration::from_secs(3600)), 60);
+        assert!(signed_ids(&nodes).is_empty());
+
+        let nodes = generate(SignaturePolicy::OnFlush, 60);
+        assert!(signed_ids(&nodes).is_empty());
+
+        // Flushing signs the next processed node.
+        let mut sb: Buffer = SendBuffer::new(signer());
+        sb.set_signature_policy(SignaturePolicy::OnFlush);
+        sb.push_pkts(0);
+        for _ in 0..3 {
+            let id = sb.next_node_id_hash();
+            assert_eq!(sb.forwards_hash(id), Ok(()));
+        }
+        sb.flush();
+        let id = sb.next_node_id_hash();
+        assert_eq!(sb.forwards_hash(id), Ok(()));
+        assert!(sb.buffer[index!(sb, id)].as_ref().unwrap().signature.is_some());
+        let id = sb.next_node_id_hash();
+        assert_eq!(sb.forwards_hash(id), Ok(()));
+        assert!(sb.buffer[index!(sb, id)].as_ref().unwrap().signature.is_none());
+    }
+
+    #[test]
+    fn test_schemes_end_to_end() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+
+        for (a, p) in [(2, 7), (4, 4), (3, 5), (2, 2)] {
+            let scheme = Scheme::new(a, p).unwrap();
+            let nodes = generate_with(scheme.clone(), SignaturePolicy::EndOfBlock, a * p * 4);
+            assert_eq!(signed_ids(&nodes).len(), 4);
+
+            let mut rb: Buffer = RecvBuf::with_scheme(scheme, Box::new(key.verifying_key()));
+            assert_eq!(rb.capacity(), (a * p + 1) * 2);
+
+            let mut nb_authenticated = 0;
+            for node in nodes {
+                assert_eq!(rb.insert(node), Ok(()));
+                nb_authenticated += rb.pop_ready_in_sequence().len();
+            }
+            assert_eq!(nb_authenticated, a * p * 4);
+        }
+    }
+
+    #[test]
+    fn test_signature_policy_bootstrap() {
+        // The receiver authenticates the whole stream from the automatic signatures.
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+
+        let mut nb_authenticated = 0;
+        for node in generate(SignaturePolicy::EndOfBlock, 60) {
+            assert_eq!(rb.insert(node), Ok(()));
+            nb_authenticated += rb.pop_ready_in_sequence().len();
+        }
+        assert_eq!(nb_authenticated, 60);
+    }
+
+    /// Generates the first `nb_nodes` nodes ready to be sent with the signature policy,
+    /// switching to the scheme when node `switch_at` is inserted.
+    /// Returns the nodes, the ID from which the new scheme applies, and the graph of the sender.
+    fn generate_switch(scheme: Scheme, switch_at: u64, policy: SignaturePolicy, nb_nodes: usize) -> (Vec<BufferEntry>, u64, Graph) {
+        let mut sb: Buffer = SendBuffer::new(signer());
+        sb.set_signature_policy(policy);
+
+        let mut nodes = Vec::new();
+        let mut start = 0;
+        let mut id = 0;
+        while nodes.len() < nb_nodes {
+            if id == switch_at {
+                start = sb.switch_scheme(scheme.clone()).unwrap();
+                assert_eq!(sb.switch_scheme(scheme.clone()), Err(Error::SwitchPending));
+            }
+
+            if sb.insert_in_sequence(BufferEntry::dummy(id)).is_ok() {
+                id += 1;
+                continue;
+            }
+
+            while sb.forwards_hash(sb.next_node_id_hash) == Ok(()) {
+                sb.next_node_id_hash();
+            }
+            nodes.extend(sb.pop_ready_in_sequence());
+        }
+        nodes.truncate(nb_nodes);
+
+        (nodes, start, sb.graph().clone())
+    }
+
+    /// Sends the nodes on the wire, encoded with the graph of the sender, except the lost ones.
+    /// Returns the receive buffer and the IDs of the authenticated nodes.
+    fn receive(nodes: &[BufferEntry], graph: &Graph, policy: ReleasePolicy, lost: impl Fn(u64) -> bool) -> (Buffer, Vec<u64>) {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        rb.set_release_policy(policy);
+
+        let mut authenticated = Vec::new();
+        for node in nodes.iter().filter(|n| !lost(n.id)) {
+            let mut buf = bytes::BytesMut::from(&node.payload.as_ref().unwrap()[..]);
+            node.encode(&mut buf, graph);
+            if let Ok(node) = rb.decode(buf.freeze(), WireFormat::Versioned) {
+                let _ = rb.insert(node);
+            }
+            authenticated.extend(rb.pop_released().iter().filter_map(|n| match n {
+                Released::Authenticated(n) => Some(n.id),
+                _ => None,
+            }));
+        }
+
+        // Nodes still in the buffer.
+        authenticated.extend(
+            rb.buffer
+                .iter()
+                .flatten()
+                .filter(|n| n.id >= rb.lowest_id && n.state == State::Authenticated)
+                .map(|n| n.id),
+        );
+        authenticated.sort();
+
+        (rb, authenticated)
+    }
+
+    #[test]
+    fn test_scheme_switch() {
+        let scheme = Scheme::new(4, 4).unwrap();
+        let (nodes, start, graph) = generate_switch(scheme.clone(), 20, SignaturePolicy::EndOfBlock, 109);
+
+        // The switch starts at the first block boundary at least a block after the last inserted node.
+        assert_eq!(start, 45);
+        for node in nodes.iter() {
+            let switch = node.switch();
+            let is_reannounced = node.signature.is_some() && (45..45 + 2 * 16).contains(&node.id);
+            assert_eq!(switch.is_some(), (20..45).contains(&node.id) || is_reannounced);
+            assert!(switch.is_none_or(|s| s == Switch { start, a: 4, p: 4 }));
+        }
+
+        // Signatures follow the blocks of each scheme.
+        assert_eq!(signed_ids(&nodes), vec![10, 25, 40, 57, 73, 89, 105]);
+
+        // The receiver transitions at the same node and authenticates the whole stream.
+        let (rb, authenticated) = receive(&nodes, &graph, ReleasePolicy::Blocking, |_| false);
+        assert_eq!(rb.scheme(), &scheme);
+        assert_eq!(rb.capacity(), scheme.capacity());
+        assert_eq!(authenticated, (0..109).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_scheme_switch_loss() {
+        let scheme = Scheme::new(4, 4).unwrap();
+        let (nodes, start, graph) = generate_switch(scheme.clone(), 20, SignaturePolicy::EveryN(1), 80);
+        assert_eq!(start, 45);
+
+        // Only the last row of announcements is received before the switch, and its signed head
+        // authenticates the switch.
+        let (rb, authenticated) = receive(&nodes, &graph, ReleasePolicy::Blocking, |id| (20..40).contains(&id));
+        assert_eq!(rb.scheme(), &scheme);
+        assert_eq!(rb.lowest_id, 20);
+        assert_eq!(authenticated, (0..20).chain(40..54).collect::<Vec<_>>());
+
+        // The first node of the new scheme is lost: the previous nodes use their other parent.
+        let (_, authenticated) = receive(&nodes, &graph, ReleasePolicy::Blocking, |id| id == 45);
+        assert_eq!(authenticated, (0..45).chain(46..79).collect::<Vec<_>>());
+
+        // All announcements are lost, or the received ones need the nodes after the switch to be
+        // authenticated: the following signed nodes announce the switch again.
+        let policy = ReleasePolicy::LossHorizon(DEFAULT_LOSS_HORIZON);
+        for lost in [20..45, 20..43] {
+            let (rb, authenticated) = receive(&nodes, &graph, policy, |id| lost.contains(&id));
+            assert_eq!(rb.scheme(), &scheme);
+            assert_eq!(authenticated, (0..20).chain(lost.end..80).collect::<Vec<_>>());
+        }
+
+        // The signed nodes announcing the switch are lost. The nodes after the switch are buffered
+        // until the next signed node announces it again, and the stream recovers.
+        let (nodes, _, graph) = generate_switch(scheme.clone(), 20, SignaturePolicy::EndOfBlock, 109);
+        let (rb, authenticated) = receive(&nodes, &graph, policy, |id| id == 25 || id == 40);
+        assert_eq!(rb.scheme(), &scheme);
+        assert_eq!(authenticated, (0..15).chain(26..40).chain(41..109).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_scheme_switch_forged() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let mut forged = generate(SignaturePolicy::EndOfBlock, 60).remove(7);
+        forged.switch = Some(Switch { start: 15, a: 64, p: 64 });
+
+        // The forged announcement arrives first, and is not applied before its node is authenticated.
+        let mut rb: Buffer = RecvBuf::new(Box::new(key.verifying_key()));
+        assert_eq!(rb.insert(forged), Ok(()));
+        assert_eq!(rb.graph(), &Graph::new(Scheme::default()));
+        assert_eq!(rb.capacity(), Scheme::default().capacity());
+
+        // The legitimate stream is authenticated, and the forged version discarded.
+        let mut nb_authenticated = 0;
+        for node in generate(SignaturePolicy::EndOfBlock, 60) {
+            let expected = if node.id == 7 { Err(Error::ConflictingDuplicate) } else { Ok(()) };
+            assert_eq!(rb.insert(node), expected);
+            nb_authenticated += rb.pop_ready_in_sequence().len();
+        }
+        assert_eq!(nb_authenticated, 60);
+        assert_eq!(rb.flood_stats().nb_forged, 1);
+        assert_eq!(rb.graph(), &Graph::new(Scheme::default()));
+        assert_eq!(rb.capacity(), Scheme::default().capacity());
+    }
 }
@@ -0,0 +1,429 @@
+//! Feedback path from the receivers to the sender.
+//!
+//! Receive buffers count the received, lost and authenticated nodes, and periodically summarize
+//! them in a compact [`Report`] sent back to the sender. The sender feeds the reports to a
+//! [`Controller`] which picks the (a, p) scheme and the signature policy fitting the losses.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use integer_encoding::VarInt;
+
+use crate::buffer::send_buf::SignaturePolicy;
+use crate::scheme::{Scheme, MAX_BLOCK_LEN};
+use crate::Error;
+use crate::Result;
+
+/// Version of the report encoding.
+const REPORT_VERSION: u8 = 1;
+
+/// Number of buckets of the burst length distribution.
+pub const NB_BURST_BUCKETS: usize = 6;
+
+/// Minimum ratio of authenticated nodes before the controller signs more often.
+/// Nodes are authenticated up to a block after their reception, so the ratio is rarely exactly 1.
+const AUTH_RATIO_TARGET: f64 = 0.9;
+
+/// Number of consecutive reports allowing a lighter scheme before the controller switches to it.
+const CALM_REPORTS: usize = 3;
+
+/// Summary of the losses and authentications observed by a receiver since its previous report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Report {
+    /// Highest node ID received.
+    pub highest_id: u64,
+
+    /// Number of received nodes.
+    pub nb_received: u64,
+
+    /// Number of nodes never received, i.e., the IDs skipped by the received ones.
+    pub nb_lost: u64,
+
+    /// Number of nodes authenticated.
+    pub nb_authenticated: u64,
+
+    /// Number of received nodes that cannot be authenticated anymore, because their signature is
+    /// invalid or all their parents are lost: the ones still in the buffer, and the ones released
+    /// by the buffer since the last report, including the expired ones.
+    pub nb_unverifiable: u64,
+
+    /// Number of loss bursts, by length: 1, 2, 3-4, 5-8, 9-16 and 17 or more.
+    pub bursts: [u64; NB_BURST_BUCKETS],
+
+    /// Length of the longest loss burst.
+    pub longest_burst: u64,
+}
+
+impl Report {
+    /// Ratio of lost nodes over the sent ones.
+    pub fn loss_rate(&self) -> f64 {
+        let nb_sent = self.nb_received + self.nb_lost;
+        if nb_sent == 0 {
+            return 0.0;
+        }
+        self.nb_lost as f64 / nb_sent as f64
+    }
+
+    /// Ratio of authenticated nodes over the received ones.
+    pub fn auth_ratio(&self) -> f64 {
+        if self.nb_received == 0 {
+            return 1.0;
+        }
+        (self.nb_authenticated as f64 / self.nb_received as f64).min(1.0)
+    }
+
+    /// Length of the longest loss burst, if any.
+    pub fn max_burst(&self) -> Option<u64> {
+        (self.longest_burst > 0).then_some(self.longest_burst)
+    }
+
+    /// Bucket of the burst length distribution holding the burst.
+    fn bucket(burst: u64) -> usize {
+        ((u64::BITS - (burst - 1).leading_zeros()) as usize).min(NB_BURST_BUCKETS - 1)
+    }
+
+    /// Encodes the report into bytes.
+    ///
+    /// The version (u8) is followed by the fields as varints, in declaration order.
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u8(REPORT_VERSION);
+        let fields = [self.highest_id, self.nb_received, self.nb_lost, self.nb_authenticated, self.nb_unverifiable];
+        for value in fields.iter().chain(self.bursts.iter()).chain([&self.longest_burst]) {
+            buf.put(&value.encode_var_vec()[..]);
+        }
+    }
+
+    /// Decodes a report from bytes.
+    /// Returns `Decoding` if the report is truncated or uses another version.
+    pub fn decode(mut buf: Bytes) -> Result<Self> {
+        if buf.is_empty() || buf.get_u8() != REPORT_VERSION {
+            return Err(Error::Decoding);
+        }
+
+        let mut next = || -> Result<u64> {
+            let (value, len) = u64::decode_var(&buf[..]).ok_or(Error::Decoding)?;
+            buf.advance(len);
+            Ok(value)
+        };
+
+        let mut report = Self {
+            highest_id: next()?,
+            nb_received: next()?,
+            nb_lost: next()?,
+            nb_authenticated: next()?,
+            nb_unverifiable: next()?,
+            bursts: [0; NB_BURST_BUCKETS],
+            longest_burst: 0,
+        };
+        for bucket in report.bursts.iter_mut() {
+            *bucket = next()?;
+        }
+        report.longest_burst = next()?;
+
+        Ok(report)
+    }
+}
+
+/// Counters of the receive buffer, reset at each report.
+#[derive(Debug, Default)]
+pub(crate) struct LossStats {
+    /// Highest node ID received, kept across reports.
+    highest_id: Option<u64>,
+
+    /// Lowest ID that may be counted as lost in the report being filled.
+    /// The missing IDs below were counted in a previous report, or were never expected.
+    first_lost: u64,
+
+    /// The report being filled.
+    report: Report,
+}
+
+impl LossStats {
+    /// Records the reception of a new node.
+    /// Nodes received after a higher ID were counted as lost, and are not anymore unless they
+    /// were counted in a previous report.
+    pub(crate) fn on_received(&mut self, id: u64) {
+        self.report.nb_received += 1;
+        match self.highest_id {
+            Some(highest) if follows(id, highest) => {
+                let burst = id.wrapping_sub(highest) - 1;
+                if burst > 0 {
+                    self.report.nb_lost += burst;
+                    self.report.bursts[Report::bucket(burst)] += 1;
+                    self.report.longest_burst = self.report.longest_burst.max(burst);
+                }
+                self.highest_id = Some(id);
+            }
+            Some(_) if id == self.first_lost || follows(id, self.first_lost) => {
+                self.report.nb_lost = self.report.nb_lost.saturating_sub(1);
+            },
+            Some(_) => (),
+            None => {
+                self.highest_id = Some(id);
+                self.first_lost = id.wrapping_add(1);
+            },
+        }
+    }
+
+    /// Records the authentication of a node.
+    pub(crate) fn on_authenticated(&mut self) {
+        self.report.nb_authenticated += 1;
+    }
+
+    /// Records the release of a node that could not be authenticated.
+    pub(crate) fn on_unverified(&mut self) {
+        self.report.nb_unverifiable += 1;
+    }
+
+    /// Highest node ID received.
+    pub(crate) fn highest_id(&self) -> Option<u64> {
+        self.highest_id
+    }
+
+    /// Whether the node was not received although a higher ID was.
+    pub(crate) fn is_lost(&self, id: u64) -> bool {
+        self.highest_id.is_some_and(|highest| follows(highest, id))
+    }
+
+    /// Produces the report and resets the counters.
+    /// The unverifiable nodes still buffered are added to the ones released since the last report.
+    pub(crate) fn take_report(&mut self, nb_unverifiable: u64) -> Report {
+        let mut report = std::mem::take(&mut self.report);
+        report.highest_id = self.highest_id.unwrap_or(0);
+        self.first_lost = self.highest_id.map_or(0, |highest| highest.wrapping_add(1));
+        report.nb_unverifiable += nb_unverifiable;
+        report
+    }
+}
+
+/// Whether the ID follows the other one, as the IDs wrap around after `u64::MAX`.
+fn follows(id: u64, other: u64) -> bool {
+    (id.wrapping_sub(other) as i64) > 0
+}
+
+/// Changes of the sender configuration decided by the controller.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Adaptation {
+    /// New scheme, to apply with `SendBuffer::switch_scheme`.
+    /// If the previous switch is still pending, call `Controller::rollback` to report it next time.
+    pub scheme: Option<Scheme>,
+
+    /// New signature policy, to apply with `SendBuffer::set_signature_policy`.
+    pub policy: Option<SignaturePolicy>,
+}
+
+/// Picks the scheme and signature policy of the sender from the reports of the receivers.
+///
+/// The `p` parameter is kept and `a` is the smallest one such that a block is longer than the
+/// longest loss burst, so that a burst cannot cut both paths of the hash of a row head.
+/// The signatures are twice as frequent each time the receivers cannot authenticate enough nodes,
+/// and twice less frequent when no loss is reported, down to one per block.
+#[derive(Debug, Clone)]
+pub struct Controller {
+    /// Current scheme of the sender.
+    scheme: Scheme,
+
+    /// Maximum `a` parameter.
+    max_a: usize,
+
+    /// Minimum number of nodes between two signatures.
+    sign_every: usize,
+
+    /// Number of consecutive reports allowing a lighter scheme.
+    nb_calm: usize,
+
+    /// Scheme, signature interval and number of calm reports before the last adaptation.
+    previous: Option<(Scheme, usize, usize)>,
+}
+
+impl Controller {
+    /// Creates a controller starting from the scheme of the sender, signing once per block.
+    /// The `a` parameter never exceeds `max_a`, nor makes a block longer than `MAX_BLOCK_LEN` nodes.
+    pub fn new(scheme: Scheme, max_a: usize) -> Self {
+        Self {
+            sign_every: scheme.a() * scheme.p(),
+            max_a: max_a.max(scheme.a()),
+            scheme,
+            nb_calm: 0,
+            previous: None,
+        }
+    }
+
+    /// The current scheme of the sender.
+    pub fn scheme(&self) -> &Scheme {
+        &self.scheme
+    }
+
+    /// The current signature policy of the sender.
+    pub fn policy(&self) -> SignaturePolicy {
+        if self.sign_every >= self.scheme.a() * self.scheme.p() {
+            SignaturePolicy::EndOfBlock
+        } else {
+            SignaturePolicy::EveryN(self.sign_every)
+        }
+    }
+
+    /// Consumes a report and returns the changes to apply to the sender, if any.
+    pub fn on_report(&mut self, report: &Report) -> Adaptation {
+        let mut adaptation = Adaptation::default();
+        if report.nb_received + report.nb_lost == 0 {
+            return adaptation;
+        }
+        self.previous = Some((self.scheme.clone(), self.sign_every, self.nb_calm));
+
+        // Scheme tolerating the longest burst.
+        let p = self.scheme.p();
+        let burst = report.max_burst().unwrap_or(0) as usize;
+        let a = (burst / p + 1).clamp(2, self.max_a.min(MAX_BLOCK_LEN / p));
+
+        // Switch to a more robust scheme at once, but wait for several calm reports for a lighter one.
+        self.nb_calm = if a < self.scheme.a() { self.nb_calm + 1 } else { 0 };
+        if a > self.scheme.a() || self.nb_calm >= CALM_REPORTS {
+            if let Ok(scheme) = Scheme::new(a, p) {
+                self.scheme = scheme.clone();
+                self.nb_calm = 0;
+                adaptation.scheme = Some(scheme);
+            }
+        }
+
+        // Signature frequency.
+        let policy = self.policy();
+        let block = self.scheme.a() * self.scheme.p();
+        if report.nb_unverifiable > 0 || report.auth_ratio() < AUTH_RATIO_TARGET {
+            self.sign_every = (self.sign_every.min(block) / 2).max(1);
+        } else if report.nb_lost == 0 {
+            self.sign_every = (self.sign_every * 2).min(block);
+        }
+        if self.policy() != policy || adaptation.scheme.is_some() {
+            adaptation.policy = Some(self.policy());
+        }
+
+        adaptation
+    }
+
+    /// Restores the state before the last report, when the sender could not apply its adaptation.
+    pub fn rollback(&mut self) {
+        if let Some((scheme, sign_every, nb_calm)) = self.previous.take() {
+            self.scheme = scheme;
+            self.sign_every = sign_every;
+            self.nb_calm = nb_calm;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_bytes() {
+        let report = Report {
+            highest_id: 1 << 40,
+            nb_received: 950,
+            nb_lost: 50,
+            nb_authenticated: 900,
+            nb_unverifiable: 3,
+            bursts: [20, 5, 3, 1, 0, 1],
+            longest_burst: 100,
+        };
+
+        let mut buf = BytesMut::new();
+        report.encode(&mut buf);
+        assert_eq!(buf.len(), 1 + 6 + 2 + 1 + 2 + 1 + 6 + 1);
+        assert_eq!(Report::decode(buf.clone().freeze()), Ok(report));
+
+        // Truncated reports and unknown versions.
+        for len in 0..buf.len() {
+            assert_eq!(Report::decode(buf.clone().freeze().slice(..len)), Err(Error::Decoding));
+        }
+        buf[0] = REPORT_VERSION + 1;
+        assert_eq!(Report::decode(buf.freeze()), Err(Error::Decoding));
+
+        assert_eq!(report.loss_rate(), 0.05);
+        assert_eq!(report.max_burst(), Some(100));
+        assert_eq!(Report::default().max_burst(), None);
+    }
+
+    #[test]
+    fn test_loss_stats() {
+        let mut stats = LossStats::default();
+        for id in [3, 4, 6, 10, 9, 11, 20, 60] {
+            stats.on_received(id);
+        }
+        stats.on_authenticated();
+        assert!(stats.is_lost(7));
+        assert!(!stats.is_lost(60));
+
+        let report = stats.take_report(2);
+        assert_eq!(report.highest_id, 60);
+        assert_eq!(report.nb_received, 8);
+        assert_eq!(report.nb_lost, 1 + 2 + 8 + 39);
+        assert_eq!(report.nb_authenticated, 1);
+        assert_eq!(report.nb_unverifiable, 2);
+        assert_eq!(report.bursts, [1, 0, 1, 1, 0, 1]);
+        assert_eq!(report.max_burst(), Some(39));
+
+        // Counters are reset, but not the highest ID.
+        let report = stats.take_report(0);
+        assert_eq!(report, Report { highest_id: 60, ..Default::default() });
+
+        // Late nodes only cancel the losses of the current report.
+        for id in [70, 65, 50] {
+            stats.on_received(id);
+        }
+        let report = stats.take_report(0);
+        assert_eq!((report.nb_received, report.nb_lost), (3, 8));
+
+        // The IDs wrap around after `u64::MAX`.
+        let mut stats = LossStats::default();
+        for id in [u64::MAX - 2, u64::MAX, 2, 1] {
+            stats.on_received(id);
+        }
+        assert!(stats.is_lost(0));
+        let report = stats.take_report(0);
+        assert_eq!((report.highest_id, report.nb_received, report.nb_lost), (2, 4, 2));
+    }
+
+    #[test]
+    fn test_controller() {
+        let mut controller = Controller::new(Scheme::default(), 6);
+        assert_eq!(controller.policy(), SignaturePolicy::EndOfBlock);
+
+        // No loss: nothing changes.
+        let calm = Report { nb_received: 100, nb_authenticated: 100, ..Default::default() };
+        assert_eq!(controller.on_report(&calm), Adaptation::default());
+
+        // Long bursts need a larger scheme, and unverifiable nodes more signatures.
+        let mut bursty = Report { nb_received: 90, nb_lost: 10, nb_authenticated: 70, ..Default::default() };
+        bursty.bursts[Report::bucket(16)] = 1;
+        bursty.longest_burst = 16;
+        let adaptation = controller.on_report(&bursty);
+        assert_eq!(adaptation.scheme, Some(Scheme::new(4, 5).unwrap()));
+        assert_eq!(adaptation.policy, Some(SignaturePolicy::EveryN(7)));
+
+        // The burst length is capped.
+        bursty.bursts[Report::bucket(100)] = 1;
+        bursty.longest_burst = 100;
+        let adaptation = controller.on_report(&bursty);
+        assert_eq!(adaptation.scheme, Some(Scheme::new(6, 5).unwrap()));
+        assert_eq!(adaptation.policy, Some(SignaturePolicy::EveryN(3)));
+
+        // The lighter scheme comes back after several calm reports.
+        for _ in 0..CALM_REPORTS - 1 {
+            let adaptation = controller.on_report(&calm);
+            assert_eq!(adaptation.scheme, None);
+        }
+        let adaptation = controller.on_report(&calm);
+        assert_eq!(adaptation.scheme, Some(Scheme::new(2, 5).unwrap()));
+        assert_eq!(adaptation.policy, Some(SignaturePolicy::EndOfBlock));
+        assert_eq!(controller.scheme(), &Scheme::new(2, 5).unwrap());
+
+        // A block never exceeds the maximum length, whatever the report.
+        let mut controller = Controller::new(Scheme::default(), 10_000);
+        let huge = Report { nb_received: 1, nb_lost: 50_000, longest_burst: 50_000, ..Default::default() };
+        let adaptation = controller.on_report(&huge);
+        assert_eq!(adaptation.scheme, Some(Scheme::new(MAX_BLOCK_LEN / 5, 5).unwrap()));
+
+        // The state before a report is restored if the sender cannot apply it.
+        controller.rollback();
+        assert_eq!(controller.scheme(), &Scheme::default());
+    }
+}
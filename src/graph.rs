@@ -0,0 +1,277 @@
+//! Dependency graph of a stream whose scheme may change over time.
+//!
+//! The stream is split in consecutive segments, each following its own (a, p) scheme from its
+//! first node ID. A new segment always starts at a block boundary of the previous one, i.e., a
+//! multiple of a×p nodes after its start. Inside a segment, IDs are relative to its start.
+//!
+//! The hashes of the last nodes of a segment that would be sent to nodes of the next segment
+//! are all sent to the first node of the next segment instead, which thus links both graphs.
+
+use crate::scheme::Scheme;
+use crate::Error;
+use crate::Result;
+
+/// In-band announcement of a scheme switch, carried by the nodes preceding the switch,
+/// and by the signed nodes of the first blocks following it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Switch {
+    /// ID of the first node following the new scheme.
+    pub start: u64,
+
+    /// The `a` parameter of the new scheme.
+    pub a: usize,
+
+    /// The `p` parameter of the new scheme.
+    pub p: usize,
+}
+
+/// Dependency graph made of consecutive segments, each following a scheme.
+/// Segments are never removed since switches are rare, and the lookup starts from the last one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Graph {
+    /// Segments, sorted by their first node ID.
+    segments: Vec<(u64, Scheme)>,
+}
+
+impl Graph {
+    /// Graph of a stream following a single scheme.
+    pub fn new(scheme: Scheme) -> Self {
+        Self::with_start(scheme, 0)
+    }
+
+    /// Graph of a stream following a single scheme, whose first node has the ID.
+    pub fn with_start(scheme: Scheme, start: u64) -> Self {
+        Self {
+            segments: vec![(start, scheme)],
+        }
+    }
+
+    /// ID of the first node of the stream.
+    /// The graph does not define lower IDs.
+    pub fn start(&self) -> u64 {
+        self.segments[0].0
+    }
+
+    /// Number of nodes from the first node of the stream to the node.
+    /// The IDs wrap around after `u64::MAX`, so the nodes are ordered by their offset.
+    pub fn offset(&self, id: u64) -> u64 {
+        id.wrapping_sub(self.start())
+    }
+
+    /// Whether the ID precedes the first node of the stream, following the serial number arithmetic:
+    /// the 2^63 IDs from the start follow it, and the others precede it.
+    pub fn precedes_start(&self, id: u64) -> bool {
+        self.offset(id) >= 1 << 63
+    }
+
+    /// Index of the segment of the node.
+    fn segment_idx(&self, id: u64) -> usize {
+        let offset = self.offset(id);
+        self.segments.iter().rposition(|(start, _)| self.offset(*start) <= offset).unwrap_or(0)
+    }
+
+    /// Start, scheme and end of the segment of the node.
+    fn segment(&self, id: u64) -> (u64, &Scheme, Option<u64>) {
+        let idx = self.segment_idx(id);
+        let (start, scheme) = &self.segments[idx];
+        (*start, scheme, self.segments.get(idx + 1).map(|(s, _)| *s))
+    }
+
+    /// The scheme followed by the node.
+    pub fn scheme_at(&self, id: u64) -> &Scheme {
+        self.segment(id).1
+    }
+
+    /// The scheme of the last segment.
+    pub fn last_scheme(&self) -> &Scheme {
+        &self.segments.last().unwrap().1
+    }
+
+    /// Number of nodes that the buffers must be able to hold for all the segments.
+    pub fn capacity(&self) -> usize {
+        self.segments.iter().map(|(_, scheme)| scheme.capacity()).max().unwrap_or(0)
+    }
+
+    /// Get the IDs of nodes that this node must send its hash to.
+    pub fn dependencies_out(&self, id: u64) -> Vec<u64> {
+        let (start, scheme, end) = self.segment(id);
+        let mut out: Vec<u64> = Vec::with_capacity(2);
+        for dep in scheme.dependencies_out(id.wrapping_sub(start)) {
+            let dep = match end {
+                Some(end) if dep >= end.wrapping_sub(start) => end,
+                _ => start.wrapping_add(dep),
+            };
+            if !out.contains(&dep) {
+                out.push(dep);
+            }
+        }
+        out
+    }
+
+    /// Get the IDs of nodes that must send their hash to this ID.
+    pub fn dependencies_in(&self, id: u64) -> Vec<u64> {
+        let idx = self.segment_idx(id);
+        let (start, scheme) = &self.segments[idx];
+        let mut deps: Vec<u64> = scheme
+            .dependencies_in(id.wrapping_sub(*start))
+            .iter()
+            .map(|dep| start.wrapping_add(*dep))
+            .collect();
+
+        // The first node of a segment receives the hashes crossing the boundary.
+        if idx > 0 && id == *start {
+            let (prev_start, prev_scheme) = &self.segments[idx - 1];
+            let len = start.wrapping_sub(*prev_start);
+            let first = len.saturating_sub((prev_scheme.a() * prev_scheme.p()) as u64);
+            let mut crossing: Vec<u64> = (first..len)
+                .filter(|&j| prev_scheme.dependencies_out(j).iter().any(|d| *d >= len))
+                .map(|j| prev_start.wrapping_add(j))
+                .collect();
+            crossing.append(&mut deps);
+            deps = crossing;
+        }
+
+        deps
+    }
+
+    /// The ID of the node forwarding its hash right after this node.
+    pub fn next_node_id_hash(&self, id: u64) -> u64 {
+        let (start, scheme, end) = self.segment(id);
+        let next = scheme.next_node_id_hash(id.wrapping_sub(start));
+        match end {
+            Some(end) if next >= end.wrapping_sub(start) => end.wrapping_add(self.scheme_at(end).first_node_id_hash()),
+            _ => start.wrapping_add(next),
+        }
+    }
+
+    /// The ID of the first node forwarding its hash.
+    pub fn first_node_id_hash(&self) -> u64 {
+        let (start, scheme) = &self.segments[0];
+        start.wrapping_add(scheme.first_node_id_hash())
+    }
+
+    /// Whether the node is the first one of its row.
+    pub fn is_row_head(&self, id: u64) -> bool {
+        let (start, scheme, _) = self.segment(id);
+        scheme.is_row_head(id.wrapping_sub(start))
+    }
+
+    /// The first node of the row of the node.
+    pub fn row_head(&self, id: u64) -> u64 {
+        let (start, scheme, _) = self.segment(id);
+        id.wrapping_sub(scheme.position(id.wrapping_sub(start)) as u64)
+    }
+
+    /// Whether the node is the head of the last row of a block of its segment.
+    pub fn is_end_of_block(&self, id: u64) -> bool {
+        let (start, scheme, _) = self.segment(id);
+        scheme.is_end_of_block(id.wrapping_sub(start))
+    }
+
+    /// The first block boundary of the last segment at or after the ID.
+    pub fn next_block_boundary(&self, id: u64) -> u64 {
+        let (start, scheme) = self.segments.last().unwrap();
+        let block = (scheme.a() * scheme.p()) as u64;
+        let len = self.offset(id).saturating_sub(self.offset(*start));
+        start.wrapping_add(len.div_ceil(block).max(1) * block)
+    }
+
+    /// Adds the scheme switch to the graph.
+    /// The switch must start at a block boundary after the start of the last segment.
+    /// Adding a switch that is already known has no effect.
+    /// Returns `InvalidScheme` otherwise.
+    pub fn switch(&mut self, switch: Switch) -> Result<()> {
+        let scheme = Scheme::new(switch.a, switch.p)?;
+        if self.segments.iter().any(|(start, s)| *start == switch.start && *s == scheme) {
+            return Ok(());
+        }
+
+        if self.next_block_boundary(switch.start) != switch.start {
+            return Err(Error::InvalidScheme);
+        }
+
+        self.segments.push((switch.start, scheme));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graph_switch() {
+        let mut graph = Graph::new(Scheme::default());
+        assert_eq!(graph.next_block_boundary(0), 15);
+        assert_eq!(graph.next_block_boundary(31), 45);
+
+        // Switches must start at block boundaries.
+        let switch = Switch { start: 40, a: 2, p: 7 };
+        assert_eq!(graph.switch(switch), Err(Error::InvalidScheme));
+        let switch = Switch { start: 45, a: 2, p: 7 };
+        assert_eq!(graph.switch(switch), Ok(()));
+        assert_eq!(graph.switch(switch), Ok(()));
+        assert_eq!(graph.next_block_boundary(45), 59);
+        assert_eq!(graph.capacity(), 32);
+
+        // The hashes crossing the boundary are sent to its first node.
+        assert_eq!(graph.dependencies_out(30), vec![35, 45]);
+        assert_eq!(graph.dependencies_out(35), vec![40, 45]);
+        assert_eq!(graph.dependencies_out(41), vec![40, 45]);
+        assert_eq!(graph.dependencies_out(44), vec![41, 45]);
+        assert_eq!(graph.dependencies_in(45), vec![30, 35, 40, 41, 44, 46]);
+
+        // The new segment is independent of the previous one.
+        assert_eq!(graph.dependencies_in(52), vec![45, 46, 51, 53]);
+        assert_eq!(graph.dependencies_out(45), vec![52, 59]);
+
+        // Nodes forward their hash only once all their in-hashes are received.
+        let mut processed = Vec::new();
+        let mut id = graph.first_node_id_hash();
+        while id < 45 + 14 * 3 {
+            for dep in graph.dependencies_in(id) {
+                assert!(processed.contains(&dep));
+                assert!(graph.dependencies_out(dep).contains(&id));
+            }
+            processed.push(id);
+            id = graph.next_node_id_hash(id);
+        }
+        processed.sort();
+        assert_eq!(processed, (0..45 + 14 * 3).collect::<Vec<_>>());
+
+        // Conflicting switches are rejected.
+        assert_eq!(graph.switch(Switch { start: 45, a: 4, p: 4 }), Err(Error::InvalidScheme));
+        assert_eq!(graph.switch(Switch { start: 59, a: 1, p: 4 }), Err(Error::InvalidScheme));
+        assert_eq!(graph.scheme_at(44), &Scheme::default());
+        assert_eq!(graph.scheme_at(45), graph.last_scheme());
+        assert_eq!(graph.row_head(44), 40);
+        assert_eq!(graph.row_head(58), 52);
+    }
+
+    #[test]
+    fn test_graph_wrap_around() {
+        // The IDs wrap around after `u64::MAX`, and the graph is the same relative to its start.
+        let start = u64::MAX - 40;
+        let mut graph = Graph::with_start(Scheme::default(), start);
+        let mut base = Graph::new(Scheme::default());
+        let switch = |graph: &Graph| Switch {
+            start: graph.next_block_boundary(graph.start().wrapping_add(31)),
+            a: 2,
+            p: 7,
+        };
+        assert_eq!(switch(&graph).start, 4);
+        graph.switch(switch(&graph)).unwrap();
+        base.switch(switch(&base)).unwrap();
+
+        let shift = |ids: Vec<u64>| ids.into_iter().map(|id| start.wrapping_add(id)).collect::<Vec<_>>();
+        for i in 0..200 {
+            let id = start.wrapping_add(i);
+            assert_eq!(graph.offset(id), i);
+            assert_eq!(graph.dependencies_in(id), shift(base.dependencies_in(i)));
+            assert_eq!(graph.dependencies_out(id), shift(base.dependencies_out(i)));
+            assert_eq!(graph.next_node_id_hash(id), start.wrapping_add(base.next_node_id_hash(i)));
+            assert_eq!(graph.row_head(id), start.wrapping_add(base.row_head(i)));
+            assert_eq!(graph.scheme_at(id), base.scheme_at(i));
+        }
+    }
+}
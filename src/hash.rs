@@ -0,0 +1,127 @@
+//! Hash algorithms used to chain the nodes of the graph.
+//!
+//! The digest length is driven by the algorithm, so that constrained deployments can
+//! trade security margin for per-packet overhead.
+
+use std::fmt::Debug;
+
+use sha2::Digest;
+
+/// Hash algorithm used to compute the total hash of a node.
+pub trait PacketHasher {
+    /// Identifier of the algorithm, as encoded on the wire.
+    const ID: u8;
+
+    /// Length of the digest, in bytes.
+    const DIGEST_LEN: usize;
+
+    /// Digest produced by the algorithm.
+    type Output: AsRef<[u8]> + Copy + Eq + Debug + for<'a> TryFrom<&'a [u8]>;
+
+    /// Hashes the concatenation of all the chunks.
+    fn digest(chunks: &[&[u8]]) -> Self::Output;
+}
+
+/// SHA-256, 32-byte digests. Default algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Sha256;
+
+impl PacketHasher for Sha256 {
+    const ID: u8 = 1;
+
+    const DIGEST_LEN: usize = 32;
+
+    type Output = [u8; 32];
+
+    fn digest(chunks: &[&[u8]]) -> Self::Output {
+        let mut hasher = sha2::Sha256::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// SHA-256 truncated to its first 80 bits, 10-byte digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Sha256Trunc80;
+
+impl PacketHasher for Sha256Trunc80 {
+    const ID: u8 = 2;
+
+    const DIGEST_LEN: usize = 10;
+
+    type Output = [u8; 10];
+
+    fn digest(chunks: &[&[u8]]) -> Self::Output {
+        let full = Sha256::digest(chunks);
+        let mut out = [0u8; 10];
+        out.copy_from_slice(&full[..10]);
+        out
+    }
+}
+
+/// SHA-384, 48-byte digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Sha384;
+
+impl PacketHasher for Sha384 {
+    const ID: u8 = 3;
+
+    const DIGEST_LEN: usize = 48;
+
+    type Output = [u8; 48];
+
+    fn digest(chunks: &[&[u8]]) -> Self::Output {
+        let mut hasher = sha2::Sha384::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// BLAKE3, 32-byte digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Blake3;
+
+impl PacketHasher for Blake3 {
+    const ID: u8 = 4;
+
+    const DIGEST_LEN: usize = 32;
+
+    type Output = [u8; 32];
+
+    fn digest(chunks: &[&[u8]]) -> Self::Output {
+        let mut hasher = blake3::Hasher::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_len<H: PacketHasher>() {
+        let out = H::digest(&[b"alta"]);
+        assert_eq!(out.as_ref().len(), H::DIGEST_LEN);
+
+        // Chunking does not change the digest.
+        assert_eq!(H::digest(&[b"al", b"ta"]), out);
+    }
+
+    #[test]
+    fn test_hashers() {
+        check_len::<Sha256>();
+        check_len::<Sha256Trunc80>();
+        check_len::<Sha384>();
+        check_len::<Blake3>();
+
+        let full = Sha256::digest(&[b"alta"]);
+        assert_eq!(Sha256Trunc80::digest(&[b"alta"])[..], full[..10]);
+        assert_ne!(Blake3::digest(&[b"alta"]), full);
+    }
+}
@@ -1,7 +1,15 @@
-pub type PktHash = [u8; 32];
-pub type Signature = [u8; 64];
+use hash::PacketHasher;
+pub use receiver::AltaReceiver;
+pub use sender::AltaSender;
+pub use sign::Signature;
 
+/// Hash of a node, whose length depends on the hash algorithm.
+pub type PktHash<H = hash::Sha256> = <H as PacketHasher>::Output;
+
+/// Default `a` parameter of the scheme.
 const ALTA_A: usize = 3;
+
+/// Default `p` parameter of the scheme.
 const ALTA_P: usize = 5;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -29,6 +37,30 @@ pub enum Error {
 
     /// Decoding error.
     Decoding,
+
+    /// The (a, p) parameters do not define a valid scheme.
+    InvalidScheme,
+
+    /// A scheme switch is still announced, before it applies or by the signed nodes following it.
+    SwitchPending,
+
+    /// The packet uses a version of the wire format or an algorithm that is not supported.
+    Unsupported,
+
+    /// The node differs from another version of the node received earlier.
+    ConflictingDuplicate,
+
+    /// The system has no source of randomness to draw the initial ID.
+    Randomness,
+
+    /// Input/output error of the socket.
+    Io(std::io::ErrorKind),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e.kind())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -48,4 +80,16 @@ pub enum State {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub mod buffer;
\ No newline at end of file
+pub mod buffer;
+pub mod feedback;
+pub mod graph;
+pub mod hash;
+#[cfg(feature = "tokio")]
+pub mod net;
+pub mod receiver;
+pub mod scheme;
+pub mod sender;
+pub mod sign;
+pub mod sim;
+#[cfg(feature = "futures")]
+pub mod stream;
\ No newline at end of file
@@ -0,0 +1,207 @@
+//! Multicast UDP transport of an ALTA stream, with Tokio.
+//!
+//! A [`MulticastSender`] sends the packets of an [`AltaSender`] to a multicast group, and a
+//! [`MulticastReceiver`] feeds the datagrams of the group to an [`AltaReceiver`].
+//! Enabled with the `tokio` feature.
+
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+
+use crate::hash::{PacketHasher, Sha256};
+use crate::receiver::AuthenticatedPayload;
+use crate::{AltaReceiver, AltaSender, Error, Result};
+
+/// Largest payload of a UDP datagram.
+const MAX_DATAGRAM_LEN: usize = 65535;
+
+/// Sender of an ALTA stream to a multicast group.
+///
+/// The packets are sent as soon as they are ready. UDP gives no delivery guarantee, so a packet
+/// that fails to be sent is lost as if dropped by the network.
+pub struct MulticastSender<H: PacketHasher = Sha256> {
+    /// Sender producing the packets.
+    sender: AltaSender<H>,
+
+    /// Socket sending the packets.
+    socket: UdpSocket,
+
+    /// Destination of the packets.
+    group: SocketAddr,
+}
+
+impl<H: PacketHasher> MulticastSender<H> {
+    /// Creates a sender to the multicast group through the interface with the given address,
+    /// e.g., `Ipv4Addr::LOCALHOST` for the receivers on the same host.
+    /// The packets are looped back to the host, with a TTL of 1.
+    /// Must be called from a Tokio runtime.
+    pub fn bind(sender: AltaSender<H>, group: SocketAddrV4, interface: Ipv4Addr) -> Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_multicast_if_v4(&interface)?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.bind(&SocketAddrV4::new(interface, 0).into())?;
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from_std(socket.into())?;
+        Ok(Self::from_socket(sender, socket, group.into()))
+    }
+
+    /// Creates a sender over a socket configured by the caller, e.g., for IPv6 groups.
+    pub fn from_socket(sender: AltaSender<H>, socket: UdpSocket, group: SocketAddr) -> Self {
+        Self { sender, socket, group }
+    }
+
+    /// The sender producing the packets.
+    pub fn sender(&self) -> &AltaSender<H> {
+        &self.sender
+    }
+
+    /// The sender producing the packets, e.g., to switch its scheme.
+    pub fn sender_mut(&mut self) -> &mut AltaSender<H> {
+        &mut self.sender
+    }
+
+    /// The socket sending the packets, e.g., to set the TTL of the packets.
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Adds a payload to the stream, and sends the packets that are ready.
+    /// Returns its ID.
+    pub async fn send(&mut self, payload: Vec<u8>) -> Result<u64> {
+        let id = self.sender.send(payload)?;
+        self.send_ready().await?;
+        Ok(id)
+    }
+
+    /// Flushes the sender, and sends all the packets.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.sender.flush()?;
+        self.send_ready().await
+    }
+
+    /// Sends the packets that are ready.
+    async fn send_ready(&mut self) -> Result<()> {
+        while let Some(packet) = self.sender.poll_packet() {
+            self.socket.send_to(&packet, self.group).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Receiver of an ALTA stream from a multicast group.
+pub struct MulticastReceiver<H: PacketHasher = Sha256> {
+    /// Receiver authenticating the packets.
+    receiver: AltaReceiver<H>,
+
+    /// Socket receiving the packets.
+    socket: UdpSocket,
+
+    /// Reception buffer of the datagrams.
+    buf: Vec<u8>,
+}
+
+impl<H: PacketHasher> MulticastReceiver<H> {
+    /// Joins the multicast group on the interface with the given address.
+    /// The port of the group may be 0 to pick any port, see `MulticastReceiver::local_addr`.
+    /// The socket reuses the address, so that several receivers join the group on the same host.
+    /// Must be called from a Tokio runtime.
+    pub fn join(receiver: AltaReceiver<H>, group: SocketAddrV4, interface: Ipv4Addr) -> Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, group.port()).into())?;
+        socket.join_multicast_v4(group.ip(), &interface)?;
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from_std(socket.into())?;
+        Ok(Self::from_socket(receiver, socket))
+    }
+
+    /// Creates a receiver over a socket configured by the caller, e.g., for IPv6 groups.
+    pub fn from_socket(receiver: AltaReceiver<H>, socket: UdpSocket) -> Self {
+        Self {
+            receiver,
+            socket,
+            buf: vec![0; MAX_DATAGRAM_LEN],
+        }
+    }
+
+    /// The receiver authenticating the packets.
+    pub fn receiver(&self) -> &AltaReceiver<H> {
+        &self.receiver
+    }
+
+    /// The receiver authenticating the packets, e.g., to produce reports.
+    pub fn receiver_mut(&mut self) -> &mut AltaReceiver<H> {
+        &mut self.receiver
+    }
+
+    /// The local address of the socket.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Receives a single datagram and processes it, see `AltaReceiver::recv`.
+    pub async fn recv_packet(&mut self) -> Result<()> {
+        let len = self.socket.recv(&mut self.buf).await?;
+        self.receiver.recv(&self.buf[..len])
+    }
+
+    /// Returns the next authenticated payload, waiting for the packets as needed.
+    /// Packets that are rejected by the receiver are skipped, and only I/O errors are returned.
+    pub async fn recv(&mut self) -> Result<AuthenticatedPayload> {
+        loop {
+            if let Some(payload) = self.receiver.poll_payload() {
+                return Ok(payload);
+            }
+            match self.recv_packet().await {
+                Err(e @ Error::Io(_)) => return Err(e),
+                _ => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn key() -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::from_bytes(&[7; 32])
+    }
+
+    /// Joins the group on the loopback interface, with the port of the group or any port.
+    fn join(initial_id: u64, group: SocketAddrV4) -> MulticastReceiver {
+        let mut receiver: AltaReceiver = AltaReceiver::new(Box::new(key().verifying_key()));
+        receiver.set_initial_id(initial_id).unwrap();
+        MulticastReceiver::join(receiver, group, Ipv4Addr::LOCALHOST).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_multicast_loopback() {
+        let sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+        let initial_id = sender.initial_id();
+
+        // Two receivers on the same host.
+        let mut group = SocketAddrV4::new(Ipv4Addr::new(239, 255, 77, 7), 0);
+        let first = join(initial_id, group);
+        group.set_port(first.local_addr().unwrap().port());
+        let mut receivers = [first, join(initial_id, group)];
+
+        let mut sender = MulticastSender::bind(sender, group, Ipv4Addr::LOCALHOST).unwrap();
+        for i in 0..40u64 {
+            assert_eq!(sender.send(vec![i as u8; 100]).await, Ok(initial_id.wrapping_add(i)));
+        }
+        sender.flush().await.unwrap();
+
+        for receiver in receivers.iter_mut() {
+            for i in 0..40u64 {
+                let payload = tokio::time::timeout(Duration::from_secs(5), receiver.recv()).await.unwrap().unwrap();
+                assert_eq!(payload.id, initial_id.wrapping_add(i));
+            }
+            // Some padding packets of the last row may not be processed yet.
+            assert!(receiver.receiver_mut().report().nb_authenticated >= 40);
+        }
+    }
+}
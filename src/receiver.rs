@@ -0,0 +1,342 @@
+//! High-level receiver turning packets from the socket into authenticated payloads.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::buffer::bytes::WireFormat;
+use crate::buffer::observer::Observer;
+use crate::buffer::recv_buf::{DeliveryMode, FloodStats, JoinPolicy, RecvBuf, ReleasePolicy, Released};
+use crate::buffer::Buffer;
+use crate::feedback::Report;
+use crate::graph::Graph;
+use crate::hash::{PacketHasher, Sha256};
+use crate::scheme::Scheme;
+use crate::sign::Verifier;
+use crate::Result;
+
+/// Payload of a node authenticated by the receiver.
+/// The padding nodes completing the rows, see `AltaSender::flush`, are not delivered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedPayload {
+    /// ID of the node in the stream.
+    pub id: u64,
+
+    /// Payload sent by the application.
+    pub payload: Vec<u8>,
+}
+
+/// Receiver of an ALTA stream.
+///
+/// Payloads are delivered in sequence once authenticated, unless the delivery mode is
+/// `DeliveryMode::Immediate`. A node is authenticated by its signature or by an authenticated
+/// parent, so a payload may wait for the following packets of the stream.
+/// Nodes given up by the release policy or after the deadline are skipped, so the IDs of the
+/// payloads may have gaps.
+pub struct AltaReceiver<H: PacketHasher = Sha256> {
+    /// Receive buffer holding the nodes until they are authenticated.
+    buffer: Buffer<H>,
+
+    /// Authenticated payloads ready to be delivered.
+    ready: VecDeque<AuthenticatedPayload>,
+
+    /// Wire format of the packets.
+    format: WireFormat,
+}
+
+impl<H: PacketHasher> AltaReceiver<H> {
+    /// Creates a new receiver with the default a=3,p=5 scheme.
+    /// The verifier holds the public key of the sender.
+    pub fn new(verifier: Box<dyn Verifier>) -> Self {
+        Self::with_scheme(Scheme::default(), verifier)
+    }
+
+    /// Creates a new receiver with the given scheme.
+    pub fn with_scheme(scheme: Scheme, verifier: Box<dyn Verifier>) -> Self {
+        Self {
+            buffer: RecvBuf::with_scheme(scheme, verifier),
+            ready: VecDeque::new(),
+            format: WireFormat::default(),
+        }
+    }
+
+    /// The dependency graph of the stream, including the switches received so far.
+    pub fn graph(&self) -> &Graph {
+        self.buffer.graph()
+    }
+
+    /// Sets the ID of the first node of the stream, shared by the sender with the session.
+    /// Must be called before receiving packets.
+    /// The IDs wrap around after `u64::MAX`.
+    pub fn set_initial_id(&mut self, id: u64) -> Result<()> {
+        self.buffer.set_initial_id(id);
+        Ok(())
+    }
+
+    /// Sets where the receiver starts the stream, e.g., `JoinPolicy::FirstSigned` to subscribe to
+    /// the stream after its start. The initial ID of the stream is still needed to follow its graph,
+    /// and the receiver must be created with the scheme in use when joining, since it does not learn
+    /// the switches announced before.
+    /// With `WireFormat::Compact`, the IDs are only known from the signed packets, which carry
+    /// the full ID, so a late receiver starts at the first one whose signature verifies.
+    /// The default policy is `JoinPolicy::Start`.
+    pub fn set_join_policy(&mut self, policy: JoinPolicy) {
+        self.buffer.set_join_policy(policy);
+    }
+
+    /// Sets the observer of the events of the buffer, e.g., for monitoring.
+    pub fn set_observer(&mut self, observer: Box<dyn Observer>) {
+        self.buffer.set_observer(observer);
+    }
+
+    /// Sets the wire format of the packets.
+    /// The default format is `WireFormat::Versioned`.
+    pub fn set_wire_format(&mut self, format: WireFormat) {
+        self.format = format;
+    }
+
+    /// Sets the policy deciding when the receiver gives up on nodes that are not authenticated.
+    /// The default policy is `ReleasePolicy::LossHorizon(DEFAULT_LOSS_HORIZON)`.
+    pub fn set_release_policy(&mut self, policy: ReleasePolicy) {
+        self.buffer.set_release_policy(policy);
+    }
+
+    /// Sets the number of packets the receiver may buffer, to accept packets arriving after the end
+    /// of its window, e.g., under reordering. The memory used grows with the largest packets.
+    /// By default, the receiver only buffers the `(a * p + 1) * 2` packets required by the scheme.
+    pub fn set_max_capacity(&mut self, max_capacity: usize) {
+        self.buffer.set_max_capacity(max_capacity);
+    }
+
+    /// Sets the maximum number of other versions kept for a packet, before one of them is authenticated.
+    /// A further version evicts the oldest one.
+    /// The default is `DEFAULT_MAX_CANDIDATES`.
+    pub fn set_max_candidates(&mut self, max_candidates: usize) {
+        self.buffer.set_max_candidates(max_candidates);
+    }
+
+    /// Sets when the authenticated payloads are delivered.
+    /// The default mode is `DeliveryMode::InSequence`.
+    pub fn set_delivery_mode(&mut self, mode: DeliveryMode) {
+        self.buffer.set_delivery_mode(mode);
+    }
+
+    /// Sets the maximum time a payload waits for its authentication.
+    /// Payloads do not expire by default.
+    pub fn set_deadline(&mut self, deadline: Option<Duration>) {
+        self.buffer.set_deadline(deadline);
+    }
+
+    /// Processes a packet received from the sender.
+    /// The nodes authenticated thanks to this packet become available with `AltaReceiver::poll_payload`.
+    /// Duplicates of a node that is still buffered are ignored.
+    /// Returns an error if the packet cannot be decoded, if it is out of the window of the receiver,
+    /// e.g., its node is already delivered, `BadAuthentication` if it does not verify,
+    /// or `ConflictingDuplicate` if it differs from another packet of the same node.
+    pub fn recv(&mut self, packet: &[u8]) -> Result<()> {
+        let node = self.buffer.decode(Bytes::copy_from_slice(packet), self.format)?;
+        let res = self.buffer.insert(node);
+
+        // Nodes may have been authenticated even if another one failed.
+        // The padding nodes are only needed to authenticate the others.
+        for released in self.buffer.pop_released() {
+            match released {
+                Released::Authenticated(entry) if !entry.is_padding() => {
+                    self.ready.push_back(AuthenticatedPayload {
+                        id: entry.id(),
+                        payload: entry.into_payload().unwrap_or_default(),
+                    });
+                },
+                _ => (),
+            }
+        }
+
+        res
+    }
+
+    /// Returns the next authenticated payload, if any.
+    pub fn poll_payload(&mut self) -> Option<AuthenticatedPayload> {
+        self.ready.pop_front()
+    }
+
+    /// Number of authenticated payloads ready to be delivered.
+    pub fn nb_ready(&self) -> usize {
+        self.ready.len()
+    }
+
+    /// Counters of the packets suspected to be forged.
+    pub fn flood_stats(&self) -> FloodStats {
+        self.buffer.flood_stats()
+    }
+
+    /// Produces a report of the losses and authentications since the previous report.
+    pub fn report(&mut self) -> Report {
+        self.buffer.report()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::bytes::IdWidth;
+    use crate::{AltaSender, Error};
+
+    fn key() -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::from_bytes(&[7; 32])
+    }
+
+    /// Creates a receiver of the stream of the sender.
+    fn receiver(sender: &AltaSender) -> AltaReceiver {
+        let mut receiver = AltaReceiver::with_scheme(sender.graph().last_scheme().clone(), Box::new(key().verifying_key()));
+        receiver.set_initial_id(sender.initial_id()).unwrap();
+        receiver
+    }
+
+    /// Sends the payloads and flushes the sender.
+    fn send(sender: &mut AltaSender, nb_payloads: u64) -> Vec<Bytes> {
+        for i in 0..nb_payloads {
+            sender.send(vec![i as u8; 50]).unwrap();
+        }
+        sender.flush().unwrap();
+        std::iter::from_fn(|| sender.poll_packet()).collect()
+    }
+
+    #[test]
+    fn test_receiver() {
+        let mut sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+        let packets = send(&mut sender, 40);
+        assert_eq!(packets.len(), 45);
+
+        // Packets are reordered inside each row, and duplicated.
+        let mut receiver = receiver(&sender);
+        for row in packets.chunks(5) {
+            for packet in row[1..].iter().rev() {
+                assert_eq!(receiver.recv(packet), Ok(()));
+                assert_eq!(receiver.recv(packet), Ok(()));
+            }
+            assert_eq!(receiver.recv(&row[0]), Ok(()));
+        }
+
+        // The padding completing the last row is not delivered.
+        let payloads: Vec<_> = std::iter::from_fn(|| receiver.poll_payload()).collect();
+        assert_eq!(payloads.len(), 40);
+        for (i, payload) in payloads.iter().enumerate() {
+            assert_eq!(payload.id, sender.initial_id().wrapping_add(i as u64));
+            assert_eq!(payload.payload, vec![i as u8; 50]);
+        }
+
+        let report = receiver.report();
+        assert_eq!(report.nb_received, 45);
+        assert_eq!(report.nb_authenticated, 45);
+
+        assert_eq!(receiver.recv(&packets[0]), Err(Error::OutOfBoundId));
+        assert_eq!(receiver.recv(&[0; 5]), Err(Error::Decoding));
+        assert_eq!(receiver.nb_ready(), 0);
+    }
+
+    #[test]
+    fn test_receiver_loss() {
+        // The second stream wraps around after `u64::MAX`.
+        for initial_id in [1000, u64::MAX - 10] {
+            let mut sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+            sender.set_initial_id(initial_id).unwrap();
+            let packets = send(&mut sender, 40);
+
+            // The lost node does not stall the stream.
+            let mut receiver = receiver(&sender);
+            for (i, packet) in packets.iter().enumerate() {
+                if i != 7 {
+                    assert_eq!(receiver.recv(packet), Ok(()));
+                }
+            }
+
+            let ids: Vec<u64> =
+                std::iter::from_fn(|| receiver.poll_payload()).map(|p| p.id.wrapping_sub(initial_id)).collect();
+            assert!(!ids.contains(&7));
+            assert!(ids.windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(ids.last(), Some(&39));
+
+            let report = receiver.report();
+            assert_eq!(report.nb_lost, 1);
+            assert_eq!(ids.len() as u64, 39 - report.nb_unverifiable);
+        }
+    }
+
+    #[test]
+    fn test_receiver_signature_last() {
+        // Only the last row of the block is signed, and its head authenticates the whole block.
+        let scheme = Scheme::new(64, 64).unwrap();
+        let mut sender: AltaSender = AltaSender::with_scheme(scheme.clone(), Box::new(key())).unwrap();
+        let mut packets = send(&mut sender, 4096);
+        assert_eq!(packets.len(), 4096);
+
+        let mut receiver = receiver(&sender);
+        let signed = packets.remove(4096 - 64);
+        for packet in packets.iter() {
+            assert_eq!(receiver.recv(packet), Ok(()));
+        }
+        assert_eq!(receiver.nb_ready(), 0);
+
+        assert_eq!(receiver.recv(&signed), Ok(()));
+        assert_eq!(receiver.nb_ready(), 4096);
+        assert_eq!(receiver.poll_payload().map(|p| p.id), Some(sender.initial_id()));
+    }
+
+    #[test]
+    fn test_receiver_late_join() {
+        let mut sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+        let packets = send(&mut sender, 200);
+
+        // The receiver subscribes in the middle of a block, and starts at the next signed packet.
+        let mut receiver = receiver(&sender);
+        receiver.set_join_policy(JoinPolicy::FirstSigned);
+        for packet in packets[95..].iter() {
+            let _ = receiver.recv(packet);
+        }
+        let ids: Vec<u64> = std::iter::from_fn(|| receiver.poll_payload()).map(|p| p.id.wrapping_sub(sender.initial_id())).collect();
+        assert_eq!(ids, (100..200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_receiver_compact_wrap() {
+        // The 16-bit IDs on the wire wrap around during the stream.
+        let mut sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+        sender.set_initial_id(65500).unwrap();
+        sender.set_wire_format(WireFormat::Compact(IdWidth::Bits16));
+        let packets = send(&mut sender, 100);
+
+        let mut receiver = receiver(&sender);
+        receiver.set_wire_format(WireFormat::Compact(IdWidth::Bits16));
+        for packet in packets.iter() {
+            assert_eq!(receiver.recv(packet), Ok(()));
+        }
+        let ids: Vec<u64> = std::iter::from_fn(|| receiver.poll_payload()).map(|p| p.id).collect();
+        assert_eq!(ids, (65500..65600).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_receiver_compact_late_join() {
+        // The receivers subscribe more than 2^16 packets after the start, and anchor the 16-bit IDs
+        // on the full ID of the signed packets.
+        let scheme = Scheme::new(8, 8).unwrap();
+        let mut sender: AltaSender = AltaSender::with_scheme(scheme, Box::new(key())).unwrap();
+        sender.set_wire_format(WireFormat::Compact(IdWidth::Bits16));
+        let packets = send(&mut sender, 70_000);
+
+        for policy in [JoinPolicy::FirstSigned, JoinPolicy::FirstPacket] {
+            let mut receiver = receiver(&sender);
+            receiver.set_wire_format(WireFormat::Compact(IdWidth::Bits16));
+            receiver.set_join_policy(policy);
+            for packet in packets[69_500..].iter() {
+                let _ = receiver.recv(packet);
+            }
+            let initial_id = sender.initial_id();
+            let ids: Vec<u64> =
+                std::iter::from_fn(|| receiver.poll_payload()).map(|p| p.id.wrapping_sub(initial_id)).collect();
+            assert!(ids.first().is_some_and(|id| (69_500..69_564).contains(id)));
+            assert_eq!(ids.last(), Some(&69_999));
+            assert!(ids.windows(2).all(|w| w[0] + 1 == w[1]));
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Shape of the ALTA dependency graph, derived from the (a, p) parameters.
+//!
+//! Nodes are grouped in rows of `p` consecutive IDs. The first node of each row (the row head)
+//! sends its hash to the head of the next row and to the head `a` rows later. The other nodes
+//! of the row chain their hashes down to the head:
+//!
+//! - node 1 sends its hash to the head and to the head of the next row;
+//! - nodes 2..=p-2 send their hash to the previous node and to node p-1;
+//! - node p-1 sends its hash to node 1 and to the head of the next row.
+//!
+//! Node p-2 is thus the first node of the row to forward its hash, and the head is the last one.
+
+use crate::Error;
+use crate::Result;
+use crate::ALTA_A;
+use crate::ALTA_P;
+
+/// Maximum number of nodes in a block of a×p nodes.
+/// It bounds the memory of the buffers, since schemes are announced in-band.
+pub const MAX_BLOCK_LEN: usize = 4096;
+
+/// Parameters of the ALTA dependency graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scheme {
+    /// Number of rows between a head and the furthest head it sends its hash to.
+    a: usize,
+
+    /// Number of nodes in a row.
+    p: usize,
+
+    /// Offsets of the nodes that must receive the hash of a node, indexed by its position in the row.
+    out_offsets: Vec<Vec<i64>>,
+
+    /// Offsets of the nodes that must send their hash to a node, indexed by its position in the row.
+    in_offsets: Vec<Vec<i64>>,
+
+    /// Positions in the row, in the order they forward their hash.
+    hash_order: Vec<usize>,
+}
+
+impl Scheme {
+    /// Creates the dependency graph of the (a, p) parameters.
+    /// Returns `InvalidScheme` if `a` or `p` is lower than 2, or if a block exceeds `MAX_BLOCK_LEN` nodes.
+    pub fn new(a: usize, p: usize) -> Result<Self> {
+        if a < 2 || p < 2 || a.saturating_mul(p) > MAX_BLOCK_LEN {
+            return Err(Error::InvalidScheme);
+        }
+
+        let (ai, pi) = (a as i64, p as i64);
+        let out_offsets: Vec<Vec<i64>> = (0..pi)
+            .map(|k| match k {
+                0 => vec![pi, ai * pi],
+                1 => vec![-1, pi - 1],
+                k if k == pi - 1 => vec![-(pi - 2), 1],
+                k => vec![-1, pi - 1 - k],
+            })
+            .collect();
+
+        // The in-dependencies are the reverse of the out-dependencies.
+        let mut in_offsets = vec![Vec::new(); p];
+        for (k, offsets) in out_offsets.iter().enumerate() {
+            for &offset in offsets {
+                let target = (k as i64 + offset).rem_euclid(pi) as usize;
+                in_offsets[target].push(-offset);
+            }
+        }
+        in_offsets.iter_mut().for_each(|offsets| offsets.sort());
+
+        let mut hash_order: Vec<usize> = (2..p.saturating_sub(1)).rev().collect();
+        if p >= 3 {
+            hash_order.push(p - 1);
+        }
+        hash_order.extend([1, 0]);
+
+        Ok(Self {
+            a,
+            p,
+            out_offsets,
+            in_offsets,
+            hash_order,
+        })
+    }
+
+    /// The `a` parameter.
+    pub fn a(&self) -> usize {
+        self.a
+    }
+
+    /// The `p` parameter.
+    pub fn p(&self) -> usize {
+        self.p
+    }
+
+    /// Number of nodes that the buffers must be able to hold.
+    pub fn capacity(&self) -> usize {
+        (self.a * self.p + 1) * 2
+    }
+
+    /// Position of the node in its row.
+    pub fn position(&self, id: u64) -> usize {
+        (id % self.p as u64) as usize
+    }
+
+    /// Get the IDs of nodes that this node must send its hash to.
+    pub fn dependencies_out(&self, id: u64) -> Vec<u64> {
+        Self::apply(id, &self.out_offsets[self.position(id)])
+    }
+
+    /// Get the IDs of nodes that must send their hash to this ID.
+    pub fn dependencies_in(&self, id: u64) -> Vec<u64> {
+        Self::apply(id, &self.in_offsets[self.position(id)])
+    }
+
+    /// The ID of the node forwarding its hash right after this node.
+    pub fn next_node_id_hash(&self, id: u64) -> u64 {
+        let row = id - self.position(id) as u64;
+        let idx = self.hash_order.iter().position(|&k| k == self.position(id)).unwrap_or(0);
+        match self.hash_order.get(idx + 1) {
+            Some(&k) => row + k as u64,
+            None => row + self.p as u64 + self.hash_order[0] as u64,
+        }
+    }
+
+    /// The ID of the first node forwarding its hash.
+    pub fn first_node_id_hash(&self) -> u64 {
+        self.hash_order[0] as u64
+    }
+
+    /// Whether the node is the first one of its row.
+    pub fn is_row_head(&self, id: u64) -> bool {
+        self.position(id) == 0
+    }
+
+    /// Whether the node is the head of the last row of a block of a×p nodes.
+    /// It is the last node of the block to forward its hash.
+    pub fn is_end_of_block(&self, id: u64) -> bool {
+        id % (self.a * self.p) as u64 == ((self.a - 1) * self.p) as u64
+    }
+
+    fn apply(id: u64, offsets: &[i64]) -> Vec<u64> {
+        offsets.iter().filter_map(|&offset| id.checked_add_signed(offset)).collect()
+    }
+}
+
+impl Default for Scheme {
+    fn default() -> Self {
+        Self::new(ALTA_A, ALTA_P).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_scheme() {
+        // Dependencies of the original a=3,p=5 graph.
+        let scheme = Scheme::default();
+        for id in 100..110 {
+            let (dep_out, dep_in): (Vec<i64>, Vec<i64>) = match id % 5 {
+                0 => (vec![5, 15], vec![-15, -5, -4, -1, 1]),
+                1 => (vec![-1, 4], vec![1, 3]),
+                2 => (vec![-1, 2], vec![1]),
+                3 => (vec![-1, 1], vec![]),
+                _ => (vec![-3, 1], vec![-2, -1]),
+            };
+            let apply = |v: Vec<i64>| v.iter().map(|&o| (id as i64 + o) as u64).collect::<Vec<_>>();
+            assert_eq!(scheme.dependencies_out(id), apply(dep_out));
+            assert_eq!(scheme.dependencies_in(id), apply(dep_in));
+        }
+
+        assert_eq!(scheme.capacity(), 32);
+
+        let mut id = scheme.first_node_id_hash();
+        let mut order = Vec::new();
+        for _ in 0..10 {
+            order.push(id);
+            id = scheme.next_node_id_hash(id);
+        }
+        assert_eq!(order, vec![3, 2, 4, 1, 0, 8, 7, 9, 6, 5]);
+    }
+
+    #[test]
+    fn test_schemes() {
+        assert_eq!(Scheme::new(1, 5), Err(Error::InvalidScheme));
+        assert_eq!(Scheme::new(3, 1), Err(Error::InvalidScheme));
+        assert_eq!(Scheme::new(2, MAX_BLOCK_LEN), Err(Error::InvalidScheme));
+        assert!(Scheme::new(2, MAX_BLOCK_LEN / 2).is_ok());
+
+        for a in 2..6 {
+            for p in 2..10 {
+                let scheme = Scheme::new(a, p).unwrap();
+                assert_eq!(scheme.capacity(), (a * p + 1) * 2);
+
+                // Each node sends its hash to two distinct nodes, and the graph is consistent.
+                let start = (a * p * 4) as u64;
+                for id in start..start + (a * p) as u64 {
+                    let dep_out = scheme.dependencies_out(id);
+                    assert_eq!(dep_out.len(), 2);
+                    assert_ne!(dep_out[0], dep_out[1]);
+                    for out in dep_out {
+                        assert!(scheme.dependencies_in(out).contains(&id));
+                    }
+                }
+
+                // Nodes forward their hash only once all their in-hashes are received.
+                let mut processed = Vec::new();
+                let mut id = scheme.first_node_id_hash();
+                for _ in 0..a * p * 3 {
+                    assert!(scheme.dependencies_in(id).iter().all(|d| processed.contains(d)));
+                    processed.push(id);
+                    id = scheme.next_node_id_hash(id);
+                }
+
+                // All nodes are processed in sequence.
+                processed.sort();
+                assert_eq!(processed, (0..(a * p * 3) as u64).collect::<Vec<_>>());
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! High-level sender turning application payloads into packets ready for the socket.
+
+use std::collections::VecDeque;
+
+use bytes::{Bytes, BytesMut};
+
+use crate::buffer::bytes::WireFormat;
+use crate::buffer::observer::Observer;
+use crate::buffer::send_buf::{SendBuffer, SignaturePolicy};
+use crate::buffer::{Buffer, BufferEntry};
+use crate::feedback::{Controller, Report};
+use crate::graph::Graph;
+use crate::hash::{PacketHasher, Sha256};
+use crate::scheme::Scheme;
+use crate::sign::Signer;
+use crate::Error;
+use crate::Result;
+
+/// Sender of an ALTA stream.
+///
+/// Payloads get consecutive IDs from a random initial ID, that receivers learn with the session.
+/// A packet is ready once the hashes of the following nodes of its
+/// row are computed, so the last payloads wait for the row to be completed by the next ones,
+/// or by `AltaSender::flush`.
+pub struct AltaSender<H: PacketHasher = Sha256> {
+    /// Send buffer holding the nodes until their hash is forwarded.
+    buffer: Buffer<H>,
+
+    /// ID of the first payload.
+    initial_id: u64,
+
+    /// ID of the next payload.
+    next_id: u64,
+
+    /// Encoded packets ready to be sent.
+    ready: VecDeque<Bytes>,
+
+    /// Wire format of the packets.
+    format: WireFormat,
+
+    /// Last node authenticated by the signatures sent so far.
+    signed_until: Option<u64>,
+}
+
+impl<H: PacketHasher> AltaSender<H> {
+    /// Creates a new sender with the default a=3,p=5 scheme.
+    /// The signer is used to sign the total hash of the nodes.
+    /// Returns `Randomness` if the initial ID cannot be drawn.
+    pub fn new(signer: Box<dyn Signer>) -> Result<Self> {
+        Self::with_scheme(Scheme::default(), signer)
+    }
+
+    /// Creates a new sender with the given scheme.
+    /// Returns `Randomness` if the initial ID cannot be drawn.
+    pub fn with_scheme(scheme: Scheme, signer: Box<dyn Signer>) -> Result<Self> {
+        let mut buffer: Buffer<H> = SendBuffer::with_scheme(scheme, signer);
+        let initial_id = random_initial_id()?;
+        buffer.set_initial_id(initial_id);
+        Ok(Self {
+            buffer,
+            initial_id,
+            next_id: initial_id,
+            ready: VecDeque::new(),
+            format: WireFormat::default(),
+            signed_until: None,
+        })
+    }
+
+    /// ID of the first payload, random by default.
+    pub fn initial_id(&self) -> u64 {
+        self.initial_id
+    }
+
+    /// Sets the ID of the first payload.
+    /// The IDs wrap around after `u64::MAX`.
+    /// Returns `IllegalInsert` if a payload was already sent.
+    pub fn set_initial_id(&mut self, id: u64) -> Result<()> {
+        if self.next_id != self.initial_id {
+            return Err(Error::IllegalInsert);
+        }
+        self.buffer.set_initial_id(id);
+        self.initial_id = id;
+        self.next_id = id;
+        Ok(())
+    }
+
+    /// The dependency graph of the stream.
+    pub fn graph(&self) -> &Graph {
+        self.buffer.graph()
+    }
+
+    /// Sets the policy deciding which nodes are signed.
+    /// The default policy is `SignaturePolicy::EndOfBlock`.
+    pub fn set_signature_policy(&mut self, policy: SignaturePolicy) {
+        self.buffer.set_signature_policy(policy);
+    }
+
+    /// Sets the observer of the events of the buffer, e.g., for monitoring.
+    pub fn set_observer(&mut self, observer: Box<dyn Observer>) {
+        self.buffer.set_observer(observer);
+    }
+
+    /// Sets the wire format of the packets.
+    /// The default format is `WireFormat::Versioned`.
+    pub fn set_wire_format(&mut self, format: WireFormat) {
+        self.format = format;
+    }
+
+    /// Switches to the scheme at the next possible block boundary.
+    /// Returns the ID of the first node following the new scheme.
+    pub fn switch_scheme(&mut self, scheme: Scheme) -> Result<u64> {
+        self.buffer.switch_scheme(scheme)
+    }
+
+    /// Applies the changes decided by the controller from a report of the receivers.
+    /// The signature policy only changes once the scheme switches. On error, e.g., `SwitchPending`,
+    /// the sender is unchanged and the controller rolls back to its previous state.
+    pub fn adapt(&mut self, controller: &mut Controller, report: &Report) -> Result<()> {
+        let adaptation = controller.on_report(report);
+        if let Some(scheme) = adaptation.scheme {
+            if let Err(e) = self.switch_scheme(scheme) {
+                controller.rollback();
+                return Err(e);
+            }
+        }
+        if let Some(policy) = adaptation.policy {
+            self.set_signature_policy(policy);
+        }
+        Ok(())
+    }
+
+    /// Adds a payload to the stream.
+    /// Returns its ID.
+    pub fn send(&mut self, payload: Vec<u8>) -> Result<u64> {
+        let id = self.push(BufferEntry::new(self.next_id, payload))?;
+        self.buffer.forward_ready();
+        self.pop_ready();
+        Ok(id)
+    }
+
+    /// Returns the next packet ready to be sent, if any.
+    pub fn poll_packet(&mut self) -> Option<Bytes> {
+        self.ready.pop_front()
+    }
+
+    /// Number of packets ready to be sent.
+    pub fn nb_ready(&self) -> usize {
+        self.ready.len()
+    }
+
+    /// Makes all the payloads ready to be sent and authenticated by the receivers.
+    /// The current row is completed with padding nodes, and its first node is signed.
+    /// If the last complete row is not covered by a signature yet, a row of padding nodes is added.
+    /// The receivers authenticate the padding nodes, but do not deliver them.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.next_id == self.initial_id {
+            return Ok(());
+        }
+        let last_id = self.next_id.wrapping_sub(1);
+
+        let graph = self.buffer.graph();
+        let is_signed = |id: u64| graph.offset(id) >= graph.offset(last_id);
+        if graph.is_row_head(self.next_id) && self.signed_until.is_some_and(is_signed) {
+            return Ok(());
+        }
+
+        // Complete the row, or add a new one.
+        self.push(BufferEntry::padding(self.next_id))?;
+        while !self.buffer.graph().is_row_head(self.next_id) {
+            self.push(BufferEntry::padding(self.next_id))?;
+        }
+
+        // The first node of the row is processed last, and authenticates all the previous nodes.
+        self.buffer.forward_ready();
+        let head = self.buffer.graph().row_head(self.next_id.wrapping_sub(1));
+        self.buffer.sign_node(head)?;
+        self.pop_ready();
+
+        Ok(())
+    }
+
+    /// Inserts the node with the next ID in the send buffer.
+    fn push(&mut self, entry: BufferEntry<H>) -> Result<u64> {
+        let id = self.next_id;
+        self.buffer.insert_in_sequence(entry)?;
+        self.next_id = id.wrapping_add(1);
+        Ok(id)
+    }
+
+    /// Encodes the nodes ready to be sent.
+    fn pop_ready(&mut self) {
+        for entry in self.buffer.pop_ready_in_sequence() {
+            let graph = self.buffer.graph();
+            if entry.signature().is_some() && graph.is_row_head(entry.id()) {
+                let row_end = entry.id().wrapping_add(graph.scheme_at(entry.id()).p() as u64 - 1);
+                if self.signed_until.is_none_or(|id| graph.offset(row_end) > graph.offset(id)) {
+                    self.signed_until = Some(row_end);
+                }
+            }
+
+            let mut buf = BytesMut::from(entry.payload().unwrap_or_default());
+            entry.encode_with(&mut buf, graph, self.format);
+            self.ready.push_back(buf.freeze());
+        }
+    }
+}
+
+/// Random initial ID, anywhere in the ID space since the IDs wrap around.
+fn random_initial_id() -> Result<u64> {
+    let mut bytes = [0; 8];
+    getrandom::getrandom(&mut bytes).map_err(|_| Error::Randomness)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::recv_buf::RecvBuf;
+    use crate::State;
+
+    fn key() -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::from_bytes(&[7; 32])
+    }
+
+    /// Decodes the packets in a receive buffer.
+    /// Returns the authenticated nodes.
+    fn receive(packets: Vec<Bytes>, graph: &Graph) -> Vec<BufferEntry> {
+        let mut rb: Buffer = RecvBuf::with_scheme(graph.last_scheme().clone(), Box::new(key().verifying_key()));
+        rb.set_initial_id(graph.start());
+        let mut out = Vec::new();
+        for packet in packets {
+            let node = BufferEntry::decode(packet, rb.graph()).unwrap();
+            assert_eq!(rb.insert(node), Ok(()));
+            out.extend(rb.pop_ready_in_sequence());
+        }
+        out
+    }
+
+    #[test]
+    fn test_sender() {
+        let mut sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+        let initial_id = sender.initial_id();
+        assert_eq!(sender.graph().start(), initial_id);
+        let mut packets = Vec::new();
+        for i in 0..58u64 {
+            assert_eq!(sender.send(vec![i as u8; 100]), Ok(initial_id.wrapping_add(i)));
+            packets.extend(std::iter::from_fn(|| sender.poll_packet()));
+        }
+
+        // The last row waits for the next payloads.
+        assert_eq!(packets.len(), 55);
+        assert_eq!(sender.flush(), Ok(()));
+        packets.extend(std::iter::from_fn(|| sender.poll_packet()));
+        assert_eq!(packets.len(), 60);
+
+        // Nothing to do without new payloads.
+        assert_eq!(sender.flush(), Ok(()));
+        assert_eq!(sender.nb_ready(), 0);
+
+        let nodes = receive(packets, sender.graph());
+        assert_eq!(nodes.len(), 60);
+        for (i, node) in nodes.iter().enumerate() {
+            assert_eq!(node.id(), initial_id.wrapping_add(i as u64));
+            assert_eq!(node.state(), State::Authenticated);
+            let payload = if i < 58 { vec![i as u8; 100] } else { Vec::new() };
+            assert_eq!(node.payload(), Some(&payload[..]));
+            assert_eq!(node.is_padding(), i >= 58);
+        }
+    }
+
+    #[test]
+    fn test_sender_initial_id() {
+        let mut sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+        assert_eq!(sender.set_initial_id(1000), Ok(()));
+        assert_eq!(sender.send(vec![0; 10]), Ok(1000));
+        assert_eq!(sender.set_initial_id(0), Err(Error::IllegalInsert));
+
+        // The stream does not depend on the nodes before the initial ID.
+        sender.flush().unwrap();
+        let packets: Vec<Bytes> = std::iter::from_fn(|| sender.poll_packet()).collect();
+        assert_eq!(packets.len(), 5);
+        let nodes = receive(packets, sender.graph());
+        assert_eq!(nodes.iter().map(|node| node.id()).collect::<Vec<_>>(), (1000..1005).collect::<Vec<_>>());
+
+        // The IDs wrap around after `u64::MAX`.
+        for initial_id in [1 << 60, u64::MAX - 4, u64::MAX - 2] {
+            let mut sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+            assert_eq!(sender.set_initial_id(initial_id), Ok(()));
+            for _ in 0..30 {
+                sender.send(vec![0; 10]).unwrap();
+            }
+            sender.flush().unwrap();
+            let packets: Vec<Bytes> = std::iter::from_fn(|| sender.poll_packet()).collect();
+            let nodes = receive(packets, sender.graph());
+            let expected: Vec<u64> = (0..30).map(|i| initial_id.wrapping_add(i)).collect();
+            assert_eq!(nodes.iter().map(|node| node.id()).take(30).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_sender_adapt() {
+        let mut sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+        let mut controller = Controller::new(Scheme::default(), 6);
+        let bursty = Report { nb_received: 90, nb_lost: 10, nb_authenticated: 70, longest_burst: 16, ..Default::default() };
+
+        // The previous switch does not apply yet: the controller rolls back.
+        sender.switch_scheme(Scheme::new(3, 5).unwrap()).unwrap();
+        assert_eq!(sender.adapt(&mut controller, &bursty), Err(Error::SwitchPending));
+        assert_eq!(controller.scheme(), &Scheme::default());
+        assert_eq!(controller.policy(), SignaturePolicy::EndOfBlock);
+
+        // Once it applies, the adaptation does.
+        for i in 0..100 {
+            sender.send(vec![i; 10]).unwrap();
+        }
+        assert_eq!(sender.adapt(&mut controller, &bursty), Ok(()));
+        assert_eq!(controller.scheme(), &Scheme::new(4, 5).unwrap());
+        assert_eq!(controller.policy(), SignaturePolicy::EveryN(7));
+    }
+
+    #[test]
+    fn test_sender_flush() {
+        let mut sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+        sender.set_signature_policy(SignaturePolicy::OnFlush);
+        for i in 0..7 {
+            sender.send(vec![i; 10]).unwrap();
+        }
+        assert_eq!(sender.nb_ready(), 5);
+
+        // The row is completed with padding nodes.
+        sender.flush().unwrap();
+        let packets: Vec<Bytes> = std::iter::from_fn(|| sender.poll_packet()).collect();
+        assert_eq!(packets.len(), 10);
+
+        let nodes = receive(packets, sender.graph());
+        assert_eq!(nodes.len(), 10);
+        assert!(nodes[7..].iter().all(|node| node.is_padding() && node.payload() == Some(&[][..])));
+        assert!(!nodes[..7].iter().any(|node| node.is_padding()));
+        assert!(nodes[5].signature().is_some());
+
+        // A complete row not covered by a signature needs a new row.
+        for i in 10..15 {
+            sender.send(vec![i; 10]).unwrap();
+        }
+        sender.flush().unwrap();
+        assert_eq!(sender.nb_ready(), 10);
+    }
+}
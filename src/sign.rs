@@ -0,0 +1,269 @@
+//! Digital signature schemes used to bootstrap the authentication of the graph.
+//!
+//! The sender signs the total hash of some nodes with a [`Signer`], and the receiver
+//! verifies them with the matching [`Verifier`]. Ed25519 is always available; ECDSA P-256,
+//! RSA-PSS and ML-DSA are enabled with the `p256`, `rsa` and `ml-dsa` features.
+
+use crate::Error;
+use crate::Result;
+
+/// Identifier of a signature algorithm, as encoded on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum SignatureAlgorithm {
+    /// Ed25519, 64-byte signatures.
+    Ed25519 = 1,
+
+    /// ECDSA over P-256 with SHA-256, 64-byte fixed-size signatures.
+    EcdsaP256 = 2,
+
+    /// RSA-PSS with SHA-256, signatures of the size of the modulus.
+    RsaPss = 3,
+
+    /// ML-DSA-65 (FIPS 204), 3309-byte signatures.
+    MlDsa65 = 4,
+}
+
+impl TryFrom<u8> for SignatureAlgorithm {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            1 => Ok(Self::Ed25519),
+            2 => Ok(Self::EcdsaP256),
+            3 => Ok(Self::RsaPss),
+            4 => Ok(Self::MlDsa65),
+            _ => Err(Error::Decoding),
+        }
+    }
+}
+
+/// Digital signature of a node, with the algorithm used to produce it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// Algorithm used to produce the signature.
+    pub algorithm: SignatureAlgorithm,
+
+    /// Encoded signature.
+    pub bytes: Vec<u8>,
+}
+
+/// Signs the total hash of nodes on the send side.
+pub trait Signer: Send + Sync {
+    /// The algorithm of the produced signatures.
+    fn algorithm(&self) -> SignatureAlgorithm;
+
+    /// Signs the message.
+    fn sign(&self, msg: &[u8]) -> Signature;
+}
+
+/// Verifies the signature of nodes on the receive side.
+pub trait Verifier: Send + Sync {
+    /// The algorithm of the verified signatures.
+    fn algorithm(&self) -> SignatureAlgorithm;
+
+    /// Verifies the signature over the message.
+    /// Returns `BadAuthentication` if the signature is invalid or uses another algorithm.
+    fn verify(&self, msg: &[u8], signature: &Signature) -> Result<()>;
+}
+
+impl Signer for ed25519_dalek::SigningKey {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::Ed25519
+    }
+
+    fn sign(&self, msg: &[u8]) -> Signature {
+        let signature = ed25519_dalek::Signer::sign(self, msg);
+        Signature {
+            algorithm: SignatureAlgorithm::Ed25519,
+            bytes: signature.to_bytes().to_vec(),
+        }
+    }
+}
+
+impl Verifier for ed25519_dalek::VerifyingKey {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::Ed25519
+    }
+
+    fn verify(&self, msg: &[u8], signature: &Signature) -> Result<()> {
+        if signature.algorithm != SignatureAlgorithm::Ed25519 {
+            return Err(Error::BadAuthentication);
+        }
+
+        let signature =
+            ed25519_dalek::Signature::from_slice(&signature.bytes).map_err(|_| Error::BadAuthentication)?;
+        self.verify_strict(msg, &signature).map_err(|_| Error::BadAuthentication)
+    }
+}
+
+#[cfg(feature = "p256")]
+impl Signer for p256::ecdsa::SigningKey {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::EcdsaP256
+    }
+
+    fn sign(&self, msg: &[u8]) -> Signature {
+        let signature: p256::ecdsa::Signature = p256::ecdsa::signature::Signer::sign(self, msg);
+        Signature {
+            algorithm: SignatureAlgorithm::EcdsaP256,
+            bytes: signature.to_bytes().to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "p256")]
+impl Verifier for p256::ecdsa::VerifyingKey {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::EcdsaP256
+    }
+
+    fn verify(&self, msg: &[u8], signature: &Signature) -> Result<()> {
+        if signature.algorithm != SignatureAlgorithm::EcdsaP256 {
+            return Err(Error::BadAuthentication);
+        }
+
+        let signature =
+            p256::ecdsa::Signature::from_slice(&signature.bytes).map_err(|_| Error::BadAuthentication)?;
+        p256::ecdsa::signature::Verifier::verify(self, msg, &signature).map_err(|_| Error::BadAuthentication)
+    }
+}
+
+#[cfg(feature = "rsa")]
+impl Signer for rsa::pss::SigningKey<sha2::Sha256> {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::RsaPss
+    }
+
+    fn sign(&self, msg: &[u8]) -> Signature {
+        use rsa::signature::{RandomizedSigner, SignatureEncoding};
+
+        let signature = self.sign_with_rng(&mut rsa::rand_core::OsRng, msg);
+        Signature {
+            algorithm: SignatureAlgorithm::RsaPss,
+            bytes: signature.to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "rsa")]
+impl Verifier for rsa::pss::VerifyingKey<sha2::Sha256> {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::RsaPss
+    }
+
+    fn verify(&self, msg: &[u8], signature: &Signature) -> Result<()> {
+        if signature.algorithm != SignatureAlgorithm::RsaPss {
+            return Err(Error::BadAuthentication);
+        }
+
+        let signature =
+            rsa::pss::Signature::try_from(&signature.bytes[..]).map_err(|_| Error::BadAuthentication)?;
+        rsa::signature::Verifier::verify(self, msg, &signature).map_err(|_| Error::BadAuthentication)
+    }
+}
+
+#[cfg(feature = "ml-dsa")]
+impl Signer for ml_dsa::SigningKey<ml_dsa::MlDsa65> {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::MlDsa65
+    }
+
+    fn sign(&self, msg: &[u8]) -> Signature {
+        let signature = ml_dsa::Signer::sign(self, msg);
+        Signature {
+            algorithm: SignatureAlgorithm::MlDsa65,
+            bytes: signature.encode().to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "ml-dsa")]
+impl Verifier for ml_dsa::VerifyingKey<ml_dsa::MlDsa65> {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::MlDsa65
+    }
+
+    fn verify(&self, msg: &[u8], signature: &Signature) -> Result<()> {
+        if signature.algorithm != SignatureAlgorithm::MlDsa65 {
+            return Err(Error::BadAuthentication);
+        }
+
+        let signature = ml_dsa::Signature::<ml_dsa::MlDsa65>::try_from(&signature.bytes[..])
+            .map_err(|_| Error::BadAuthentication)?;
+        ml_dsa::Verifier::verify(self, msg, &signature).map_err(|_| Error::BadAuthentication)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_scheme(signer: &dyn Signer, verifier: &dyn Verifier, other: &dyn Verifier) {
+        let msg = [42u8; 32];
+        let signature = signer.sign(&msg);
+        assert_eq!(signature.algorithm, signer.algorithm());
+        assert_eq!(verifier.algorithm(), signer.algorithm());
+
+        assert_eq!(verifier.verify(&msg, &signature), Ok(()));
+
+        // Another message.
+        assert_eq!(verifier.verify(&[43u8; 32], &signature), Err(Error::BadAuthentication));
+
+        // Another key.
+        assert_eq!(other.verify(&msg, &signature), Err(Error::BadAuthentication));
+
+        // Truncated signature.
+        let mut truncated = signature.clone();
+        truncated.bytes.pop();
+        assert_eq!(verifier.verify(&msg, &truncated), Err(Error::BadAuthentication));
+
+        // Wrong algorithm.
+        let mut wrong = signature.clone();
+        wrong.algorithm = if wrong.algorithm == SignatureAlgorithm::Ed25519 {
+            SignatureAlgorithm::EcdsaP256
+        } else {
+            SignatureAlgorithm::Ed25519
+        };
+        assert_eq!(verifier.verify(&msg, &wrong), Err(Error::BadAuthentication));
+    }
+
+    #[test]
+    fn test_ed25519() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let other = ed25519_dalek::SigningKey::from_bytes(&[8; 32]);
+        check_scheme(&key, &key.verifying_key(), &other.verifying_key());
+    }
+
+    #[cfg(feature = "p256")]
+    #[test]
+    fn test_ecdsa_p256() {
+        let key = p256::ecdsa::SigningKey::from_slice(&[7; 32]).unwrap();
+        let other = p256::ecdsa::SigningKey::from_slice(&[8; 32]).unwrap();
+        check_scheme(&key, key.verifying_key(), other.verifying_key());
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn test_rsa_pss() {
+        let mut rng = rsa::rand_core::OsRng;
+        let key = rsa::RsaPrivateKey::new(&mut rng, 1024).unwrap();
+        let other = rsa::RsaPrivateKey::new(&mut rng, 1024).unwrap();
+        let signer = rsa::pss::SigningKey::<sha2::Sha256>::new(key);
+        let verifier = rsa::pss::VerifyingKey::<sha2::Sha256>::new(signer.as_ref().to_public_key());
+        let other = rsa::pss::VerifyingKey::<sha2::Sha256>::new(other.to_public_key());
+        check_scheme(&signer, &verifier, &other);
+    }
+
+    #[cfg(feature = "ml-dsa")]
+    #[test]
+    fn test_ml_dsa() {
+        let key = ml_dsa::SigningKey::<ml_dsa::MlDsa65>::from_seed(&[7; 32].into());
+        let other = ml_dsa::SigningKey::<ml_dsa::MlDsa65>::from_seed(&[8; 32].into());
+        check_scheme(
+            &key,
+            &key.expanded_key().verifying_key(),
+            &other.expanded_key().verifying_key(),
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Simulation of an ALTA stream over a lossy channel, to compare the schemes for a link.
+//!
+//! The packets of an [`AltaSender`] go through a [`LossModel`] to an [`AltaReceiver`], and the
+//! [`SimResult`] gives the ratio of authenticated nodes, the verification delay and the overhead.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::buffer::recv_buf::{DeliveryMode, ReleasePolicy};
+use crate::buffer::send_buf::SignaturePolicy;
+use crate::scheme::Scheme;
+use crate::{AltaReceiver, AltaSender};
+
+/// Decides which packets are lost by the channel.
+pub trait LossModel {
+    /// Whether the next packet is lost.
+    fn is_lost(&mut self) -> bool;
+}
+
+/// Small pseudo-random generator (SplitMix64), so that the simulations are reproducible from a seed.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform draw in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Independent losses with the same probability.
+#[derive(Debug, Clone)]
+pub struct Bernoulli {
+    loss_rate: f64,
+    rng: Rng,
+}
+
+impl Bernoulli {
+    /// Loses each packet with the given probability.
+    pub fn new(loss_rate: f64, seed: u64) -> Self {
+        Self {
+            loss_rate,
+            rng: Rng(seed),
+        }
+    }
+}
+
+impl LossModel for Bernoulli {
+    fn is_lost(&mut self) -> bool {
+        self.rng.next_f64() < self.loss_rate
+    }
+}
+
+/// Bursts of losses, following a two-state Markov chain.
+/// The channel goes from the good state to the bad one with probability `p`, and back with
+/// probability `r`. Packets are lost with a probability depending on the state.
+#[derive(Debug, Clone)]
+pub struct GilbertElliott {
+    p: f64,
+    r: f64,
+    loss_good: f64,
+    loss_bad: f64,
+    is_bad: bool,
+    rng: Rng,
+}
+
+impl GilbertElliott {
+    /// Creates the channel in the good state.
+    pub fn new(p: f64, r: f64, loss_good: f64, loss_bad: f64, seed: u64) -> Self {
+        Self {
+            p,
+            r,
+            loss_good,
+            loss_bad,
+            is_bad: false,
+            rng: Rng(seed),
+        }
+    }
+
+    /// The Gilbert model, losing all the packets in the bad state and none in the good one.
+    /// The mean length of the bursts is `1 / r`.
+    pub fn gilbert(p: f64, r: f64, seed: u64) -> Self {
+        Self::new(p, r, 0.0, 1.0, seed)
+    }
+
+    /// Loss rate in the long run.
+    pub fn mean_loss_rate(&self) -> f64 {
+        let bad = self.p / (self.p + self.r);
+        bad * self.loss_bad + (1.0 - bad) * self.loss_good
+    }
+}
+
+impl LossModel for GilbertElliott {
+    fn is_lost(&mut self) -> bool {
+        let switch = if self.is_bad { self.r } else { self.p };
+        if self.rng.next_f64() < switch {
+            self.is_bad = !self.is_bad;
+        }
+        let loss = if self.is_bad { self.loss_bad } else { self.loss_good };
+        self.rng.next_f64() < loss
+    }
+}
+
+/// Replays a trace of losses, e.g., captured on a link, from its start once it is over.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    losses: Vec<bool>,
+    next: usize,
+}
+
+impl Trace {
+    /// Replays the losses, `true` for a lost packet.
+    /// An empty trace loses nothing.
+    pub fn new(losses: Vec<bool>) -> Self {
+        Self { losses, next: 0 }
+    }
+
+    /// Parses a trace with a character per packet, `1` if lost and `0` if received.
+    /// Whitespaces are ignored.
+    pub fn parse(trace: &str) -> io::Result<Self> {
+        let losses = trace
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| match c {
+                '0' => Ok(false),
+                '1' => Ok(true),
+                _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid trace character {c:?}"))),
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self::new(losses))
+    }
+
+    /// Reads the trace from a file, see `Trace::parse`.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+impl LossModel for Trace {
+    fn is_lost(&mut self) -> bool {
+        let Some(&lost) = self.losses.get(self.next) else {
+            return false;
+        };
+        self.next = (self.next + 1) % self.losses.len();
+        lost
+    }
+}
+
+/// Parameters of a simulated stream.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    /// Scheme of the stream.
+    pub scheme: Scheme,
+
+    /// Decides which nodes are signed.
+    pub signature_policy: SignaturePolicy,
+
+    /// Decides when the receiver gives up on the nodes.
+    pub release_policy: ReleasePolicy,
+
+    /// Number of payloads sent.
+    pub nb_payloads: usize,
+
+    /// Length of the payloads.
+    pub payload_len: usize,
+}
+
+impl Simulation {
+    /// Simulates 10000 payloads of 1000 bytes with the scheme, signed at the end of each block.
+    pub fn new(scheme: Scheme) -> Self {
+        Self {
+            scheme,
+            signature_policy: SignaturePolicy::EndOfBlock,
+            release_policy: ReleasePolicy::default(),
+            nb_payloads: 10000,
+            payload_len: 1000,
+        }
+    }
+
+    /// Sends the stream through the channel.
+    pub fn run(&self, channel: &mut dyn LossModel) -> SimResult {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[42; 32]);
+        let mut sender: AltaSender = AltaSender::with_scheme(self.scheme.clone(), Box::new(key.clone())).unwrap();
+        sender.set_initial_id(0).unwrap();
+        sender.set_signature_policy(self.signature_policy);
+        let mut receiver: AltaReceiver = AltaReceiver::with_scheme(self.scheme.clone(), Box::new(key.verifying_key()));
+        receiver.set_initial_id(0).unwrap();
+        receiver.set_release_policy(self.release_policy);
+        receiver.set_delivery_mode(DeliveryMode::Immediate);
+
+        let mut res = SimResult::default();
+        let mut total_delay = 0;
+        let mut id = 0;
+        let mut transmit = |packet: bytes::Bytes| {
+            res.nb_sent += 1;
+            res.nb_bytes += packet.len() as u64;
+            if !channel.is_lost() {
+                res.nb_received += 1;
+                let _ = receiver.recv(&packet);
+            }
+
+            // The payloads are delivered as soon as they are authenticated.
+            while let Some(payload) = receiver.poll_payload() {
+                res.nb_authenticated += 1;
+                let delay = id - payload.id;
+                res.max_delay = res.max_delay.max(delay);
+                total_delay += delay;
+            }
+            id += 1;
+        };
+
+        for _ in 0..self.nb_payloads {
+            sender.send(vec![0; self.payload_len]).unwrap();
+            while let Some(packet) = sender.poll_packet() {
+                transmit(packet);
+            }
+        }
+        sender.flush().unwrap();
+        while let Some(packet) = sender.poll_packet() {
+            transmit(packet);
+        }
+
+        res.nb_payload_bytes = (self.nb_payloads * self.payload_len) as u64;
+        res.mean_delay = if res.nb_authenticated > 0 {
+            total_delay as f64 / res.nb_authenticated as f64
+        } else {
+            0.0
+        };
+        res
+    }
+}
+
+/// Outcome of a simulation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimResult {
+    /// Number of packets sent, including the padding of the last row.
+    pub nb_sent: u64,
+
+    /// Number of packets received.
+    pub nb_received: u64,
+
+    /// Number of payloads authenticated, without the padding of the last row.
+    pub nb_authenticated: u64,
+
+    /// Number of bytes sent.
+    pub nb_bytes: u64,
+
+    /// Number of bytes of the payloads of the application.
+    pub nb_payload_bytes: u64,
+
+    /// Mean number of packets sent between a packet and its authentication.
+    pub mean_delay: f64,
+
+    /// Maximum number of packets sent between a packet and its authentication.
+    pub max_delay: u64,
+}
+
+impl SimResult {
+    /// Probability that a received packet is authenticated.
+    pub fn auth_probability(&self) -> f64 {
+        if self.nb_received == 0 {
+            return 0.0;
+        }
+        self.nb_authenticated as f64 / self.nb_received as f64
+    }
+
+    /// Mean number of bytes added to each packet, for the hashes, the signatures and the trailer,
+    /// and including the padding of the last row.
+    pub fn overhead(&self) -> f64 {
+        if self.nb_sent == 0 {
+            return 0.0;
+        }
+        (self.nb_bytes - self.nb_payload_bytes) as f64 / self.nb_sent as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loss_models() {
+        let rate = |model: &mut dyn LossModel| (0..100_000).filter(|_| model.is_lost()).count() as f64 / 100_000.0;
+
+        assert!((rate(&mut Bernoulli::new(0.1, 1)) - 0.1).abs() < 0.01);
+
+        let mut channel = GilbertElliott::gilbert(0.02, 0.25, 1);
+        let expected = channel.mean_loss_rate();
+        assert!((rate(&mut channel) - expected).abs() < 0.01);
+
+        let mut trace = Trace::parse("0010\n01").unwrap();
+        let losses: Vec<bool> = (0..8).map(|_| trace.is_lost()).collect();
+        assert_eq!(losses, [false, false, true, false, false, true, false, false]);
+        assert!(Trace::parse("0x1").is_err());
+        assert!(!Trace::new(Vec::new()).is_lost());
+    }
+
+    #[test]
+    fn test_simulation() {
+        let mut sim = Simulation::new(Scheme::default());
+        sim.nb_payloads = 300;
+
+        // Without losses, each block is authenticated by its signature.
+        let res = sim.run(&mut Trace::new(Vec::new()));
+        assert_eq!(res.nb_sent, 300);
+        assert_eq!(res.nb_authenticated, 300);
+        assert_eq!(res.auth_probability(), 1.0);
+        assert!(res.max_delay < 2 * 15);
+        assert!(res.overhead() > 64.0 / 15.0);
+
+        // The results only depend on the seed.
+        let res = sim.run(&mut Bernoulli::new(0.1, 7));
+        assert_eq!(res, sim.run(&mut Bernoulli::new(0.1, 7)));
+        assert!(res.auth_probability() > 0.8 && res.auth_probability() < 1.0, "{res:?}");
+    }
+}
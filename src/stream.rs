@@ -0,0 +1,240 @@
+//! Adapters of the sender and receiver to the `Sink` and `Stream` traits of the futures crate.
+//!
+//! An [`AltaSink`] takes the payloads of the application and forwards the packets of the
+//! stream to an inner sink, e.g., a socket. An [`AltaStream`] takes the packets from an inner
+//! stream and yields the authenticated payloads.
+//! Enabled with the `futures` feature.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::hash::{PacketHasher, Sha256};
+use crate::receiver::AuthenticatedPayload;
+use crate::{AltaReceiver, AltaSender, Error};
+
+/// Sink of the payloads of an ALTA stream, forwarding its packets to an inner sink.
+///
+/// The sink is ready for the next payload once all the packets ready are accepted by the inner
+/// sink, so a slow inner sink slows the application down instead of filling the send window.
+/// Flushing only sends the packets ready, since the sink may be flushed after each payload.
+/// Closing completes the current row of the stream, see `AltaSender::flush`, which may also be
+/// called with `AltaSink::sender_mut` to send the last payloads before closing.
+pub struct AltaSink<S, H: PacketHasher = Sha256> {
+    /// Sender producing the packets.
+    sender: AltaSender<H>,
+
+    /// Destination of the packets.
+    inner: S,
+}
+
+impl<S, H: PacketHasher> AltaSink<S, H> {
+    /// Creates a sink of payloads sending the packets of the sender to the inner sink.
+    pub fn new(sender: AltaSender<H>, inner: S) -> Self {
+        Self { sender, inner }
+    }
+
+    /// The sender producing the packets.
+    pub fn sender(&self) -> &AltaSender<H> {
+        &self.sender
+    }
+
+    /// The sender producing the packets, e.g., to switch its scheme.
+    pub fn sender_mut(&mut self) -> &mut AltaSender<H> {
+        &mut self.sender
+    }
+
+    /// Returns the sender and the inner sink.
+    pub fn into_inner(self) -> (AltaSender<H>, S) {
+        (self.sender, self.inner)
+    }
+}
+
+// The sender is never pinned.
+impl<S: Unpin, H: PacketHasher> Unpin for AltaSink<S, H> {}
+
+impl<S, H> AltaSink<S, H>
+where
+    S: Sink<Bytes> + Unpin,
+    H: PacketHasher,
+    Error: From<S::Error>,
+{
+    /// Forwards the packets ready to the inner sink, as long as it accepts them.
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        while self.sender.nb_ready() > 0 {
+            match Pin::new(&mut self.inner).poll_ready(cx) {
+                Poll::Ready(Ok(())) => (),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+                Poll::Pending => return Poll::Pending,
+            }
+            let packet = self.sender.poll_packet().unwrap();
+            Pin::new(&mut self.inner).start_send(packet)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S, H> Sink<Bytes> for AltaSink<S, H>
+where
+    S: Sink<Bytes> + Unpin,
+    H: PacketHasher,
+    Error: From<S::Error>,
+{
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.get_mut().poll_send_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, payload: Bytes) -> Result<(), Error> {
+        self.get_mut().sender.send(payload.into()).map(|_| ())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        match this.poll_send_ready(cx) {
+            Poll::Ready(Ok(())) => (),
+            other => return other,
+        }
+        Pin::new(&mut this.inner).poll_flush(cx).map_err(Error::from)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.sender.flush()?;
+        match self.as_mut().poll_flush(cx) {
+            Poll::Ready(Ok(())) => (),
+            other => return other,
+        }
+        Pin::new(&mut self.get_mut().inner).poll_close(cx).map_err(Error::from)
+    }
+}
+
+/// Stream of the authenticated payloads of an ALTA stream, from the packets of an inner stream.
+///
+/// Packets rejected by the receiver are skipped, see `AltaReceiver::recv`.
+/// The stream ends with the inner stream, and the payloads not authenticated by then are lost.
+pub struct AltaStream<S, H: PacketHasher = Sha256> {
+    /// Receiver authenticating the packets.
+    receiver: AltaReceiver<H>,
+
+    /// Source of the packets.
+    inner: S,
+}
+
+impl<S, H: PacketHasher> AltaStream<S, H> {
+    /// Creates a stream of the payloads authenticated by the receiver from the packets of the inner stream.
+    pub fn new(receiver: AltaReceiver<H>, inner: S) -> Self {
+        Self { receiver, inner }
+    }
+
+    /// The receiver authenticating the packets.
+    pub fn receiver(&self) -> &AltaReceiver<H> {
+        &self.receiver
+    }
+
+    /// The receiver authenticating the packets, e.g., to produce reports.
+    pub fn receiver_mut(&mut self) -> &mut AltaReceiver<H> {
+        &mut self.receiver
+    }
+
+    /// Returns the receiver and the inner stream.
+    pub fn into_inner(self) -> (AltaReceiver<H>, S) {
+        (self.receiver, self.inner)
+    }
+}
+
+// The receiver is never pinned.
+impl<S: Unpin, H: PacketHasher> Unpin for AltaStream<S, H> {}
+
+impl<S, H> Stream for AltaStream<S, H>
+where
+    S: Stream<Item = Bytes> + Unpin,
+    H: PacketHasher,
+{
+    type Item = AuthenticatedPayload;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<AuthenticatedPayload>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(payload) = this.receiver.poll_payload() {
+                return Poll::Ready(Some(payload));
+            }
+            match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(Some(packet)) => {
+                    let _ = this.receiver.recv(&packet);
+                },
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::mpsc;
+    use futures::task::noop_waker_ref;
+    use futures::{SinkExt, StreamExt};
+
+    use super::*;
+
+    fn key() -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::from_bytes(&[7; 32])
+    }
+
+    #[test]
+    fn test_stream() {
+        let sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+        let mut receiver: AltaReceiver = AltaReceiver::new(Box::new(key().verifying_key()));
+        receiver.set_initial_id(sender.initial_id()).unwrap();
+        let initial_id = sender.initial_id();
+
+        let (tx, rx) = mpsc::channel(4);
+        let mut sink = AltaSink::new(sender, tx.sink_map_err(|_| Error::Io(std::io::ErrorKind::BrokenPipe)));
+        let stream = AltaStream::new(receiver, rx);
+
+        let send = async {
+            for i in 0..40u8 {
+                sink.send(Bytes::from(vec![i; 100])).await.unwrap();
+            }
+            sink.close().await.unwrap();
+        };
+        let (_, payloads) = futures::executor::block_on(futures::future::join(send, stream.collect::<Vec<_>>()));
+
+        assert_eq!(payloads.len(), 40);
+        for (i, payload) in payloads.iter().enumerate() {
+            assert_eq!(payload.id, initial_id.wrapping_add(i as u64));
+            assert_eq!(payload.payload, vec![i as u8; 100]);
+        }
+    }
+
+    #[test]
+    fn test_sink_backpressure() {
+        let sender: AltaSender = AltaSender::new(Box::new(key())).unwrap();
+        let (tx, mut rx) = mpsc::channel::<Bytes>(0);
+        let mut sink = AltaSink::new(sender, tx.sink_map_err(|_| Error::Io(std::io::ErrorKind::BrokenPipe)));
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        // The first row is ready with its last payload. The inner channel holds a single packet,
+        // and the others wait in the sender.
+        let mut nb_payloads = 0;
+        while Pin::new(&mut sink).poll_ready(&mut cx).is_ready() {
+            Pin::new(&mut sink).start_send(Bytes::from(vec![nb_payloads; 10])).unwrap();
+            nb_payloads += 1;
+        }
+        assert_eq!(nb_payloads, 5);
+        let nb_waiting = sink.sender().nb_ready();
+        assert!(nb_waiting > 0);
+
+        // The sink is ready again once the packets are taken.
+        let mut nb_packets = 0;
+        while Pin::new(&mut sink).poll_ready(&mut cx).is_pending() {
+            assert!(rx.try_recv().is_ok());
+            nb_packets += 1;
+        }
+        assert_eq!(nb_packets, nb_waiting);
+    }
+}